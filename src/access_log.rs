@@ -0,0 +1,346 @@
+// Access log lines in the Common Log Format, the Combined Log Format or as
+// JSON, one per request, written to stdout or to a file that can rotate by size.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+use crate::{
+    error,
+    httpdate::{format_rfc3339, DateTime},
+    listing::escape_json,
+    request::Request,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    // host ident user [time] "request" status bytes
+    Common,
+    // Common plus "referer" "user-agent" and the latency in microseconds
+    Combined,
+    // One JSON object per line
+    Json,
+}
+
+impl AccessLogFormat {
+    pub fn parse(value: &str) -> Option<AccessLogFormat> {
+        match value.to_lowercase().as_str() {
+            "common" | "clf" => Some(AccessLogFormat::Common),
+            "combined" => Some(AccessLogFormat::Combined),
+            "json" => Some(AccessLogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// Everything logged about one request. `request` is None when the request
+// couldn't be parsed and was rejected before it got that far.
+pub struct AccessEntry<'a> {
+    pub client: Option<IpAddr>,
+    pub time: SystemTime,
+    pub request: Option<&'a Request>,
+    pub status: u16,
+    pub bytes: u64,
+    pub latency: Duration,
+}
+
+impl AccessEntry<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.request.and_then(|request| request.header(name))
+    }
+
+    // One log line, without the trailing newline
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Common => self.format_common(),
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\" {}",
+                self.format_common(),
+                escape_log(self.header("Referer").unwrap_or("-")),
+                escape_log(self.header("User-Agent").unwrap_or("-")),
+                self.latency.as_micros()
+            ),
+            AccessLogFormat::Json => self.format_json(),
+        }
+    }
+
+    fn format_common(&self) -> String {
+        let date = DateTime::from_system_time(self.time);
+        let request_line = match self.request {
+            Some(request) => format!("{} {} {}", request.method, request.target, request.version),
+            None => "-".to_string(),
+        };
+        format!(
+            "{} - - [{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000] \"{}\" {} {}",
+            self.client_text(),
+            date.day,
+            date.month_name(),
+            date.year,
+            date.hour,
+            date.minute,
+            date.second,
+            escape_log(&request_line),
+            self.status,
+            // CLF writes "-" rather than 0 for an empty body
+            if self.bytes == 0 { "-".to_string() } else { self.bytes.to_string() }
+        )
+    }
+
+    fn format_json(&self) -> String {
+        let string_or_null = |value: Option<&str>| match value {
+            Some(value) => format!("\"{}\"", escape_json(value)),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"client\":\"{}\",\"time\":\"{}\",\"method\":{},\"path\":{},\"protocol\":{},\"status\":{},\"bytes\":{},\"referer\":{},\"user_agent\":{},\"latency_ms\":{:.3}}}",
+            self.client_text(),
+            format_rfc3339(self.time),
+            string_or_null(self.request.map(|request| request.method.as_str())),
+            string_or_null(self.request.map(|request| request.target.raw.as_str())),
+            string_or_null(self.request.map(|request| request.version.as_str())),
+            self.status,
+            self.bytes,
+            string_or_null(self.header("Referer")),
+            string_or_null(self.header("User-Agent")),
+            self.latency.as_secs_f64() * 1000.0
+        )
+    }
+
+    fn client_text(&self) -> String {
+        match self.client {
+            Some(ip) => ip.to_string(),
+            None => "-".to_string(),
+        }
+    }
+}
+
+// Where log lines go
+enum Sink {
+    Disabled,
+    Stdout,
+    File {
+        file: File,
+        path: PathBuf,
+        // Bytes written so far, to know when to rotate
+        size: u64,
+    },
+}
+
+// Shared by all workers, each line is written with a single locked write
+pub struct AccessLog {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    format: AccessLogFormat,
+    // Rotate once the file would grow past this many bytes, 0 never rotates
+    max_size: u64,
+    // Rotated files kept as access.log.1 .. access.log.N
+    keep: usize,
+    sink: Sink,
+}
+
+impl AccessLog {
+    // A log that drops every entry
+    pub fn disabled() -> AccessLog {
+        AccessLog::new(AccessLogFormat::Common, 0, 0, Sink::Disabled)
+    }
+
+    // Open the log described by `path`: "-" is stdout, anything else a file
+    // that is appended to
+    pub fn open(path: &Path, format: AccessLogFormat, max_size: u64, keep: usize) -> Result<AccessLog, String> {
+        let sink = if path == Path::new("-") {
+            Sink::Stdout
+        } else {
+            let file = open_append(path)
+                .map_err(|e| format!("cannot open access log {:?}: {}", path, e))?;
+            let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            Sink::File {
+                file,
+                path: path.to_path_buf(),
+                size,
+            }
+        };
+        Ok(AccessLog::new(format, max_size, keep, sink))
+    }
+
+    fn new(format: AccessLogFormat, max_size: u64, keep: usize, sink: Sink) -> AccessLog {
+        AccessLog {
+            inner: Mutex::new(Inner {
+                format,
+                max_size,
+                keep,
+                sink,
+            }),
+        }
+    }
+
+    // Switch this log over to `other` in place, so connections already holding
+    // it write to the new file and no two handles ever rotate the same one.
+    // Reload uses it even when nothing changed, to pick up a file moved away
+    // by logrotate.
+    pub fn replace(&self, other: AccessLog) {
+        let other = other.inner.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *self.lock() = other;
+    }
+
+    pub fn log(&self, entry: &AccessEntry) {
+        let mut inner = self.lock();
+        let Inner {
+            format,
+            max_size,
+            keep,
+            sink,
+        } = &mut *inner;
+        if matches!(sink, Sink::Disabled) {
+            return;
+        }
+
+        let mut line = entry.format(*format);
+        line.push('\n');
+
+        let result = match sink {
+            Sink::Disabled => Ok(()),
+            Sink::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            Sink::File { file, path, size } => {
+                if *max_size > 0 && *size > 0 && *size + line.len() as u64 > *max_size {
+                    match rotate(path, *keep) {
+                        Ok(new_file) => {
+                            *file = new_file;
+                            *size = 0;
+                        }
+                        Err(e) => error!("Failed to rotate access log {:?}: {}", path, e),
+                    }
+                }
+                *size += line.len() as u64;
+                file.write_all(line.as_bytes())
+            }
+        };
+        if let Err(e) = result {
+            error!("Failed to write access log: {}", e);
+        }
+    }
+
+    // Push out anything still buffered, used at shutdown
+    pub fn flush(&self) {
+        let result = match &mut self.lock().sink {
+            Sink::Disabled => Ok(()),
+            Sink::Stdout => io::stdout().flush(),
+            Sink::File { file, .. } => file.sync_data(),
+        };
+        if let Err(e) = result {
+            error!("Failed to flush access log: {}", e);
+        }
+    }
+
+    // A worker panicked mid-write, the log itself is still usable
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Shift access.log.N-1 to access.log.N and so on, move the current file to
+// access.log.1 and start a fresh one. With keep = 0 the old lines are dropped.
+fn rotate(path: &Path, keep: usize) -> io::Result<File> {
+    let numbered = |n: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+
+    if keep == 0 {
+        fs::remove_file(path)?;
+    } else {
+        for n in (1..keep).rev() {
+            let from = numbered(n);
+            if from.exists() {
+                fs::rename(&from, numbered(n + 1))?;
+            }
+        }
+        fs::rename(path, numbered(1))?;
+    }
+    open_append(path)
+}
+
+// Quote-safe text for the CLF fields, escaping like Apache does
+fn escape_log(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessEntry<'static> {
+        AccessEntry {
+            client: None,
+            time: SystemTime::UNIX_EPOCH,
+            request: None,
+            status: 400,
+            bytes: 0,
+            latency: Duration::ZERO,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("simple_http_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn replace_reopens_a_moved_file() {
+        let dir = temp_dir("access_log_replace");
+        let path = dir.join("access.log");
+        let log = AccessLog::open(&path, AccessLogFormat::Common, 0, 0).unwrap();
+        log.log(&entry());
+
+        // What logrotate does before sending SIGHUP
+        fs::rename(&path, dir.join("access.log.old")).unwrap();
+        log.replace(AccessLog::open(&path, AccessLogFormat::Json, 0, 0).unwrap());
+        log.log(&entry());
+
+        let old = fs::read_to_string(dir.join("access.log.old")).unwrap();
+        let new = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(old.lines().count(), 1);
+        assert!(old.contains("\" 400 "));
+        assert_eq!(new.lines().count(), 1);
+        assert!(new.starts_with('{'));
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = temp_dir("access_log_rotate");
+        let path = dir.join("access.log");
+        let line_length = entry().format(AccessLogFormat::Common).len() as u64 + 1;
+        let log = AccessLog::open(&path, AccessLogFormat::Common, line_length * 2, 2).unwrap();
+        for _ in 0..7 {
+            log.log(&entry());
+        }
+
+        let lines = |name: &str| fs::read_to_string(dir.join(name)).map(|text| text.lines().count()).ok();
+        let counts = (lines("access.log"), lines("access.log.1"), lines("access.log.2"));
+        let third = dir.join("access.log.3").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(counts, (Some(1), Some(2), Some(2)));
+        assert!(!third);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::deflate;
+
+// Content codings this server understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+    // Compressing on the fly needs the "brotli" feature, precompressed
+    // .br files can be served without it
+    Brotli,
+}
+
+impl Encoding {
+    // Token used in Accept-Encoding and Content-Encoding
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+// Codings `compress` can produce, in order of preference when the client
+// rates them equally
+#[cfg(feature = "brotli")]
+pub const RUNTIME_ENCODINGS: &[Encoding] = &[Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+#[cfg(not(feature = "brotli"))]
+pub const RUNTIME_ENCODINGS: &[Encoding] = &[Encoding::Gzip, Encoding::Deflate];
+
+// Suffixes of precompressed sibling files, e.g. style.css.br, best first
+pub const PRECOMPRESSED_SUFFIXES: &[(Encoding, &str)] = &[(Encoding::Brotli, "br"), (Encoding::Gzip, "gz")];
+
+// Whether compressing this type is worthwhile; images, video and archives
+// are already compressed
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+                | "image/x-icon"
+        )
+}
+
+// Pick the best of the `available` codings from an Accept-Encoding header,
+// honoring q-values, identity's included. A coding has to be rated higher
+// than identity, or equal when the client didn't name identity itself.
+// Falls back to identity when nothing else is acceptable.
+pub fn negotiate(accept_encoding: Option<&str>, available: &[Encoding]) -> Encoding {
+    let header = match accept_encoding {
+        Some(header) => header,
+        None => return Encoding::Identity,
+    };
+
+    let preferences = parse_accept_encoding(header);
+    let quality_of = |name: &str| {
+        preferences
+            .iter()
+            .find(|(coding, _)| coding == name)
+            .or_else(|| preferences.iter().find(|(coding, _)| coding == "*"))
+            .map(|(_, quality)| *quality)
+            .unwrap_or(0.0)
+    };
+
+    let identity_named = preferences.iter().any(|(coding, _)| coding == "identity");
+    let mut best = Encoding::Identity;
+    let mut best_quality = quality_of(Encoding::Identity.name());
+    for &encoding in available {
+        let quality = quality_of(encoding.name());
+        let beats_identity_tie = quality == best_quality && best == Encoding::Identity && !identity_named;
+        if quality > 0.0 && (quality > best_quality || beats_identity_tie) {
+            best = encoding;
+            best_quality = quality;
+        }
+    }
+    best
+}
+
+// Split "gzip;q=0.8, br, *;q=0" into lowercase (coding, q) pairs
+fn parse_accept_encoding(header: &str) -> Vec<(String, f32)> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let coding = params.next()?.trim().to_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+
+            let mut quality = 1.0;
+            for param in params {
+                if let Some((name, value)) = param.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        quality = value.trim().parse::<f32>().ok()?.clamp(0.0, 1.0);
+                    }
+                }
+            }
+            Some((coding, quality))
+        })
+        .collect()
+}
+
+// Encode `data`, None when this build can't produce the coding
+pub fn compress(data: &[u8], encoding: Encoding) -> Option<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Some(data.to_vec()),
+        Encoding::Gzip => Some(deflate::gzip(data)),
+        Encoding::Deflate => Some(deflate::zlib(data)),
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => Some(brotli_compress(data)),
+        #[cfg(not(feature = "brotli"))]
+        Encoding::Brotli => None,
+    }
+}
+
+#[cfg(feature = "brotli")]
+fn brotli_compress(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    // Quality 5 with a 4 MiB window is a good speed/ratio balance for live responses
+    let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+    writer
+        .write_all(data)
+        .expect("writing to an in-memory buffer cannot fail");
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AVAILABLE: &[Encoding] = &[Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    fn pick(header: &str) -> Encoding {
+        negotiate(Some(header), AVAILABLE)
+    }
+
+    #[test]
+    fn parses_codings_and_qualities() {
+        assert_eq!(
+            parse_accept_encoding("GZIP;q=0.8, br , *;Q=0, ,deflate;q=2"),
+            [
+                ("gzip".to_string(), 0.8),
+                ("br".to_string(), 1.0),
+                ("*".to_string(), 0.0),
+                ("deflate".to_string(), 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_quality_drops_the_coding() {
+        assert_eq!(parse_accept_encoding("gzip;q=high, br"), [("br".to_string(), 1.0)]);
+        assert_eq!(pick("gzip;q=high"), Encoding::Identity);
+    }
+
+    #[test]
+    fn highest_quality_wins() {
+        assert_eq!(pick("gzip;q=0.5, deflate;q=0.9"), Encoding::Deflate);
+        assert_eq!(pick("gzip, deflate, br"), Encoding::Brotli);
+        assert_eq!(pick("deflate, gzip"), Encoding::Gzip);
+        assert_eq!(pick("gzip;q=0.5"), Encoding::Gzip);
+    }
+
+    #[test]
+    fn no_header_or_nothing_acceptable_is_identity() {
+        assert_eq!(negotiate(None, AVAILABLE), Encoding::Identity);
+        assert_eq!(pick(""), Encoding::Identity);
+        assert_eq!(pick("compress, zstd"), Encoding::Identity);
+        assert_eq!(negotiate(Some("gzip"), &[]), Encoding::Identity);
+    }
+
+    #[test]
+    fn zero_quality_excludes() {
+        assert_eq!(pick("gzip;q=0"), Encoding::Identity);
+        assert_eq!(pick("br;q=0, gzip;q=0.3"), Encoding::Gzip);
+        assert_eq!(pick("*;q=0, identity"), Encoding::Identity);
+    }
+
+    #[test]
+    fn wildcard_covers_unnamed_codings() {
+        assert_eq!(pick("*"), Encoding::Brotli);
+        assert_eq!(pick("br;q=0, *;q=0.5"), Encoding::Gzip);
+        assert_eq!(pick("*;q=0.5, deflate;q=0.6"), Encoding::Deflate);
+    }
+
+    #[test]
+    fn identity_quality_is_respected() {
+        assert_eq!(pick("identity;q=1, gzip;q=0.5"), Encoding::Identity);
+        assert_eq!(pick("gzip;q=0.1, identity"), Encoding::Identity);
+        assert_eq!(pick("identity, gzip"), Encoding::Identity);
+        assert_eq!(pick("identity;q=0.5, gzip"), Encoding::Gzip);
+        assert_eq!(pick("identity;q=0, gzip;q=0.1"), Encoding::Gzip);
+    }
+}
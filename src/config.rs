@@ -0,0 +1,890 @@
+use std::{
+    env,
+    fs,
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use crate::{
+    access_log::{AccessLog, AccessLogFormat},
+    log::{Level, LogFilter},
+    mime::MimeTypes,
+    request::Limits,
+    sandbox::SymlinkPolicy,
+};
+
+// Prefix for environment variable overrides, e.g. SIMPLE_HTTP_PORT=9000
+const ENV_PREFIX: &str = "SIMPLE_HTTP_";
+
+pub const USAGE: &str = "\
+Usage: simple_http_server [OPTIONS]
+
+Options:
+  -c, --config <FILE>             Read settings from a config file
+  -b, --bind <ADDR>               Address to listen on (default 127.0.0.1:8080)
+  -p, --port <PORT>               Port to listen on, keeping the bind host
+  -l, --listen <ADDR[,OPTIONS]>   Listen on ADDR instead of --bind (repeatable), e.g.
+                                  [::]:8443,tls or 0.0.0.0:8081,root=/srv/other, or a Unix
+                                  socket as unix:/run/site.sock,mode=660. Options:
+                                  root=DIR, tls (use --tls-cert/--tls-key),
+                                  tls_cert=FILE, tls_key=FILE and mode=OCTAL (Unix sockets)
+  -r, --root <DIR>                Directory to serve files from
+      --index <FILES>             Comma-separated index files tried in each directory
+                                  (default index.html,index.htm,default.html)
+      --directory-listing <BOOL>  List directories that have no index file (default false)
+      --symlinks <POLICY>         never, within-root or always (default within-root)
+      --compression <BOOL>        Compress text responses for clients that accept it (default true)
+      --compression-min-size <BYTES>
+                                  Smallest file worth compressing (default 1024)
+      --compression-max-size <BYTES>
+                                  Largest file compressed on the fly, bigger files are
+                                  streamed as-is (default 8388608)
+      --precompressed <BOOL>      Serve file.br / file.gz siblings when accepted (default true)
+      --mime-types <FILE>         Extra extension to type mappings in mime.types format
+      --content-sniffing <BOOL>   Guess the type of files with unknown extensions from their
+                                  first bytes instead of sending nosniff (default false)
+      --tls-cert <FILE>           PEM certificate chain, serves HTTPS instead of HTTP
+      --tls-key <FILE>            PEM private key for --tls-cert
+      --tls-sni <HOST=CERT,KEY>   Certificate for clients asking for HOST, e.g.
+                                  example.com=example.pem,example.key (repeatable)
+      --redirect-http <ADDR>      Also listen for plain HTTP on ADDR and redirect it to HTTPS
+      --access-log <FILE>         Write an access log line per request, - for stdout
+                                  (default off)
+      --access-log-format <FORMAT>
+                                  common, combined or json (default combined)
+      --access-log-max-size <BYTES>
+                                  Rotate the access log past this size, 0 never rotates
+                                  (default 0)
+      --access-log-keep <N>       Rotated access logs kept as FILE.1 .. FILE.N (default 5)
+      --log-level <FILTER>        error, warn, info, debug, trace or off, optionally per module,
+                                  e.g. info,server=debug (default info)
+      --log-timestamps <BOOL>     Prefix diagnostic messages with the time (default true)
+  -q, --quiet                     Only log errors, same as --log-level error
+      --workers <N>               Worker threads handling connections
+      --queue-capacity <N>        Connections waiting for a worker before accept blocks
+      --keep-alive-timeout <SECS> Idle time before a persistent connection is closed
+      --watch-config <BOOL>       Reload when the config or MIME types file changes, as on
+                                  SIGHUP (default false)
+      --drain-timeout <SECS>      Time open connections get to finish at shutdown (default 10)
+      --max-requests <N>          Requests served on one connection
+      --max-request-line <BYTES>  Longest request line accepted (414 beyond)
+      --max-header-size <BYTES>   Total header bytes accepted (431 beyond)
+      --max-header-count <N>      Header lines accepted (431 beyond)
+      --max-body-size <BYTES>     Largest request body accepted (413 beyond)
+  -h, --help                      Print this help
+
+Every option can also be set in the config file as `key = value`
+(e.g. `keep_alive_timeout = 5`) or through a SIMPLE_HTTP_<KEY>
+environment variable (e.g. SIMPLE_HTTP_KEEP_ALIVE_TIMEOUT=5).
+Command-line flags override environment variables, which override the file.
+The TLS options need a build with `--features tls`.
+";
+
+// One address to listen on, from --listen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenConfig {
+    // e.g. "0.0.0.0:8080", "[::1]:8080", "[::]:443" or "unix:/run/site.sock"
+    pub address: String,
+    // Document root for this address instead of pages_dir
+    pub pages_dir: Option<PathBuf>,
+    // Serve HTTPS, with the certificate below or else the global tls_cert/tls_key
+    pub tls: bool,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    // Permissions for a Unix socket file, e.g. 0o660
+    pub mode: Option<u32>,
+}
+
+impl ListenConfig {
+    // Socket file path for "unix:PATH" addresses
+    pub fn unix_path(&self) -> Option<&Path> {
+        self.address.strip_prefix("unix:").map(Path::new)
+    }
+}
+
+// A certificate picked by the server name the client asks for (SNI)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SniCertificate {
+    // Lowercase host name, or a wildcard like *.example.com
+    pub hostname: String,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+// Settings that only take effect on restart, a reload just reports them
+pub const RESTART_SETTINGS: &[&str] = &[
+    "bind_address",
+    "listen",
+    "tls_cert",
+    "tls_key",
+    "tls_sni",
+    "redirect_http",
+    "workers",
+    "queue_capacity",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // File the settings were read from, if any, re-read on reload
+    pub config_file: Option<PathBuf>,
+    pub bind_address: String,
+    // Replaces bind_address when not empty
+    pub listen: Vec<ListenConfig>,
+    pub pages_dir: PathBuf,
+    pub index_files: Vec<String>,
+    pub directory_listing: bool,
+    pub symlinks: SymlinkPolicy,
+    pub compression: bool,
+    pub compression_min_size: u64,
+    pub compression_max_size: u64,
+    pub precompressed: bool,
+    pub mime_types: MimeTypes,
+    pub content_sniffing: bool,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_sni: Vec<SniCertificate>,
+    pub redirect_http: Option<String>,
+    pub access_log: Option<PathBuf>,
+    pub access_log_format: AccessLogFormat,
+    pub access_log_max_size: u64,
+    pub access_log_keep: usize,
+    pub log_filter: LogFilter,
+    pub log_timestamps: bool,
+    pub workers: usize,
+    pub queue_capacity: usize,
+    pub keep_alive_timeout: Duration,
+    pub max_requests_per_connection: usize,
+    pub drain_timeout: Duration,
+    pub watch_config: bool,
+    pub limits: Limits,
+    // SIMPLE_HTTP_* variables that aren't settings, warned about once logging is set up
+    pub unknown_env_vars: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            config_file: None,
+            bind_address: "127.0.0.1:8080".to_string(),
+            listen: Vec::new(),
+            pages_dir: get_pages_directory(),
+            index_files: vec![
+                "index.html".to_string(),
+                "index.htm".to_string(),
+                "default.html".to_string(),
+            ],
+            directory_listing: false,
+            symlinks: SymlinkPolicy::WithinRoot,
+            compression: true,
+            compression_min_size: 1024,
+            compression_max_size: 8 * 1024 * 1024,
+            precompressed: true,
+            mime_types: MimeTypes::default(),
+            content_sniffing: false,
+            tls_cert: None,
+            tls_key: None,
+            tls_sni: Vec::new(),
+            redirect_http: None,
+            access_log: None,
+            access_log_format: AccessLogFormat::Combined,
+            access_log_max_size: 0,
+            access_log_keep: 5,
+            log_filter: LogFilter::new(Level::Info),
+            log_timestamps: true,
+            workers: 4,
+            queue_capacity: 64,
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests_per_connection: 100,
+            drain_timeout: Duration::from_secs(10),
+            watch_config: false,
+            limits: Limits::default(),
+            unknown_env_vars: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // Build the configuration from the process arguments and environment
+    pub fn load() -> Result<Config, String> {
+        let args: Vec<String> = env::args().skip(1).collect();
+        let env_vars: Vec<(String, String)> = env::vars().collect();
+        Config::from_sources(&args, &env_vars)
+    }
+
+    // Layer defaults, config file, environment and command line, then validate
+    pub fn from_sources(args: &[String], env_vars: &[(String, String)]) -> Result<Config, String> {
+        let cli = parse_args(args)?;
+        let mut config = Config::default();
+
+        // The config file can be named on the command line or in the environment
+        let env_config = env_vars
+            .iter()
+            .find(|(name, _)| name == &format!("{}CONFIG", ENV_PREFIX))
+            .map(|(_, value)| value.as_str());
+        let config_file = cli
+            .iter()
+            .find(|(key, _)| key == "config")
+            .map(|(_, value)| value.as_str())
+            .or(env_config);
+        if let Some(path) = config_file {
+            config.apply_file(Path::new(path))?;
+            config.config_file = Some(PathBuf::from(path));
+        }
+
+        for (name, value) in env_vars {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                let key = key.to_lowercase();
+                if key == "config" {
+                    continue;
+                }
+                // Other programs may share the prefix, so unknown names are not fatal
+                let known = config
+                    .try_set(&key, value)
+                    .map_err(|e| format!("environment variable {}: {}", name, e))?;
+                if !known {
+                    config.unknown_env_vars.push(name.clone());
+                }
+            }
+        }
+
+        for (key, value) in &cli {
+            if key == "config" {
+                continue;
+            }
+            config
+                .set(key, value)
+                .map_err(|e| format!("option --{}: {}", key.replace('_', "-"), e))?;
+        }
+
+        config.validate()?;
+
+        // Sandboxing compares resolved paths against the canonical root
+        config.pages_dir = canonical_dir(&config.pages_dir)?;
+        for listen in &mut config.listen {
+            if let Some(pages_dir) = &listen.pages_dir {
+                listen.pages_dir = Some(canonical_dir(pages_dir)?);
+            }
+        }
+        Ok(config)
+    }
+
+    // Read `key = value` lines from a TOML-like file, ignoring comments and section headers
+    fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {:?}: {}", path, e))?;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                format!("{:?} line {}: expected `key = value`", path, number + 1)
+            })?;
+            let value = unquote(value.trim());
+            self.set(key.trim(), value)
+                .map_err(|e| format!("{:?} line {}: {}", path, number + 1, e))?;
+        }
+
+        Ok(())
+    }
+
+    // Apply a single setting by name, shared by the file, environment and CLI
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if self.try_set(key, value)? {
+            Ok(())
+        } else {
+            Err(format!("unknown setting {:?}", key))
+        }
+    }
+
+    // Like `set`, but reports an unknown key as `Ok(false)` instead of an error
+    fn try_set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key.replace('-', "_").as_str() {
+            "bind" | "bind_address" => self.bind_address = value.to_string(),
+            // Each use adds addresses, several can be given separated by ';'
+            "listen" => {
+                for entry in value.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+                    self.listen.push(parse_listen(entry)?);
+                }
+            }
+            "port" => {
+                let port: u16 = parse_number(value)?;
+                let host = match self.bind_address.rsplit_once(':') {
+                    Some((host, _)) => host.to_string(),
+                    None => self.bind_address.clone(),
+                };
+                self.bind_address = format!("{}:{}", host, port);
+            }
+            "root" | "pages_dir" => self.pages_dir = PathBuf::from(value),
+            "index" | "index_files" => {
+                self.index_files = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "directory_listing" => self.directory_listing = parse_bool(value)?,
+            "symlinks" => {
+                self.symlinks = SymlinkPolicy::parse(value)
+                    .ok_or_else(|| format!("unknown symlink policy {:?}", value))?
+            }
+            "compression" => self.compression = parse_bool(value)?,
+            "compression_min_size" => self.compression_min_size = parse_number(value)?,
+            "compression_max_size" => self.compression_max_size = parse_number(value)?,
+            "precompressed" => self.precompressed = parse_bool(value)?,
+            "mime_types" => self.mime_types.load_overrides(Path::new(value))?,
+            "content_sniffing" => self.content_sniffing = parse_bool(value)?,
+            "tls_cert" => self.tls_cert = Some(PathBuf::from(value)),
+            "tls_key" => self.tls_key = Some(PathBuf::from(value)),
+            // Each use adds certificates, several can be given separated by ';'
+            "tls_sni" => {
+                for entry in value.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+                    self.tls_sni.push(parse_sni_certificate(entry)?);
+                }
+            }
+            "redirect_http" => self.redirect_http = Some(value.to_string()),
+            "access_log" => {
+                self.access_log = match value.trim() {
+                    "" | "off" => None,
+                    path => Some(PathBuf::from(path)),
+                }
+            }
+            "access_log_format" => {
+                self.access_log_format = AccessLogFormat::parse(value)
+                    .ok_or_else(|| format!("unknown access log format {:?}", value))?
+            }
+            "access_log_max_size" => self.access_log_max_size = parse_number(value)?,
+            "access_log_keep" => self.access_log_keep = parse_number(value)?,
+            "log_level" | "log" => self.log_filter = LogFilter::parse(value)?,
+            "log_timestamps" => self.log_timestamps = parse_bool(value)?,
+            "quiet" => {
+                if parse_bool(value)? {
+                    self.log_filter = LogFilter::new(Level::Error);
+                }
+            }
+            "workers" => self.workers = parse_number(value)?,
+            "queue_capacity" => self.queue_capacity = parse_number(value)?,
+            "keep_alive_timeout" => {
+                self.keep_alive_timeout = Duration::from_secs(parse_number(value)?)
+            }
+            "watch_config" => self.watch_config = parse_bool(value)?,
+            "drain_timeout" => self.drain_timeout = Duration::from_secs(parse_number(value)?),
+            "max_requests" | "max_requests_per_connection" => {
+                self.max_requests_per_connection = parse_number(value)?
+            }
+            "max_request_line" => self.limits.max_request_line = parse_number(value)?,
+            "max_header_size" => self.limits.max_header_size = parse_number(value)?,
+            "max_header_count" => self.limits.max_header_count = parse_number(value)?,
+            "max_body_size" => self.limits.max_body_size = parse_number(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Settings that differ from `old`, as (name, old value, new value)
+    pub fn changes_from(&self, old: &Config) -> Vec<(&'static str, String, String)> {
+        let mut changes = Vec::new();
+        macro_rules! compare {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    let (before, after) = (format!("{:?}", old.$field), format!("{:?}", self.$field));
+                    if before != after {
+                        changes.push((stringify!($field), before, after));
+                    }
+                )*
+            };
+        }
+        compare!(
+            bind_address,
+            listen,
+            pages_dir,
+            index_files,
+            directory_listing,
+            symlinks,
+            compression,
+            compression_min_size,
+            compression_max_size,
+            precompressed,
+            content_sniffing,
+            tls_cert,
+            tls_key,
+            tls_sni,
+            redirect_http,
+            access_log,
+            access_log_format,
+            access_log_max_size,
+            access_log_keep,
+            log_filter,
+            log_timestamps,
+            workers,
+            queue_capacity,
+            keep_alive_timeout,
+            max_requests_per_connection,
+            drain_timeout,
+            watch_config,
+            limits,
+        );
+
+        // HashMap's Debug order isn't stable, so compare the overrides directly
+        if self.mime_types != old.mime_types {
+            changes.push((
+                "mime_types",
+                format!("{} overrides from {:?}", old.mime_types.override_count(), old.mime_types.files()),
+                format!("{} overrides from {:?}", self.mime_types.override_count(), self.mime_types.files()),
+            ));
+        }
+        changes
+    }
+
+    // Take the RESTART_SETTINGS from the running configuration, so a reload
+    // doesn't pretend to have applied them
+    pub fn keep_restart_settings(&mut self, running: &Config) {
+        self.bind_address = running.bind_address.clone();
+        self.listen = running.listen.clone();
+        self.tls_cert = running.tls_cert.clone();
+        self.tls_key = running.tls_key.clone();
+        self.tls_sni = running.tls_sni.clone();
+        self.redirect_http = running.redirect_http.clone();
+        self.workers = running.workers;
+        self.queue_capacity = running.queue_capacity;
+    }
+
+    // The addresses to listen on: the --listen entries, or else bind_address
+    // with the global TLS settings
+    pub fn listeners(&self) -> Vec<ListenConfig> {
+        if !self.listen.is_empty() {
+            return self.listen.clone();
+        }
+        vec![ListenConfig {
+            address: self.bind_address.clone(),
+            pages_dir: None,
+            tls: self.tls_cert.is_some() || !self.tls_sni.is_empty(),
+            tls_cert: None,
+            tls_key: None,
+            mode: None,
+        }]
+    }
+
+    // Whether any listener speaks HTTPS
+    pub fn tls_enabled(&self) -> bool {
+        self.listeners().iter().any(|listen| listen.tls)
+    }
+
+    // The access log these settings describe, opened for appending
+    pub fn open_access_log(&self) -> Result<AccessLog, String> {
+        match &self.access_log {
+            Some(path) => AccessLog::open(path, self.access_log_format, self.access_log_max_size, self.access_log_keep),
+            None => Ok(AccessLog::disabled()),
+        }
+    }
+
+    // Catch bad settings before the server tries to bind
+    fn validate(&self) -> Result<(), String> {
+        for listen in self.listeners() {
+            if listen.unix_path().is_some() {
+                if !cfg!(unix) {
+                    return Err(format!("{} needs a platform with Unix sockets", listen.address));
+                }
+                if listen.tls {
+                    return Err(format!("{} can't use tls, only TCP addresses can", listen.address));
+                }
+            } else {
+                check_address("listen", &listen.address)?;
+                if listen.mode.is_some() {
+                    return Err(format!("mode only applies to unix: addresses, not {}", listen.address));
+                }
+            }
+
+            if let Some(pages_dir) = &listen.pages_dir {
+                if !pages_dir.is_dir() {
+                    return Err(format!("pages directory for {} does not exist: {:?}", listen.address, pages_dir));
+                }
+            }
+
+            if listen.tls_cert.is_some() != listen.tls_key.is_some() {
+                return Err(format!("tls_cert and tls_key for {} have to be set together", listen.address));
+            }
+            if listen.tls && listen.tls_cert.is_none() && self.tls_cert.is_none() && self.tls_sni.is_empty() {
+                return Err(format!("{} uses tls but no tls_cert is configured", listen.address));
+            }
+        }
+
+        if !self.pages_dir.is_dir() {
+            return Err(format!(
+                "pages directory does not exist: {:?}\nPlease create a 'pages' folder with web files",
+                self.pages_dir
+            ));
+        }
+
+        for index_file in &self.index_files {
+            if index_file.contains(['/', '\\']) || index_file == "." || index_file == ".." {
+                return Err(format!("index file must be a plain file name, got {:?}", index_file));
+            }
+        }
+
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("tls_cert and tls_key have to be set together".to_string());
+        }
+
+        if self.tls_enabled() && !cfg!(feature = "tls") {
+            return Err("TLS settings need a build with `--features tls`".to_string());
+        }
+
+        if let Some(redirect) = &self.redirect_http {
+            if !self.tls_enabled() {
+                return Err("redirect_http needs TLS to be configured".to_string());
+            }
+            check_address("redirect_http", redirect)?;
+        }
+
+        if self.workers == 0 {
+            return Err("workers must be at least 1".to_string());
+        }
+
+        if self.keep_alive_timeout.is_zero() {
+            return Err("keep_alive_timeout must be at least 1 second".to_string());
+        }
+
+        if self.max_requests_per_connection == 0 {
+            return Err("max_requests must be at least 1".to_string());
+        }
+
+        if self.limits.max_request_line < 16 {
+            return Err("max_request_line must be at least 16 bytes".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+// Turn command-line flags into (key, value) pairs
+fn parse_args(args: &[String]) -> Result<Vec<(String, String)>, String> {
+    let mut settings = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        // The one flag that takes no value
+        if flag == "-q" || flag == "--quiet" {
+            settings.push(("quiet".to_string(), "true".to_string()));
+            continue;
+        }
+
+        let key = match flag {
+            "-c" | "--config" => "config",
+            "-b" | "--bind" => "bind",
+            "-p" | "--port" => "port",
+            "-l" | "--listen" => "listen",
+            "-r" | "--root" => "root",
+            "--index" => "index",
+            "--directory-listing" => "directory_listing",
+            "--symlinks" => "symlinks",
+            "--compression" => "compression",
+            "--compression-min-size" => "compression_min_size",
+            "--compression-max-size" => "compression_max_size",
+            "--precompressed" => "precompressed",
+            "--mime-types" => "mime_types",
+            "--content-sniffing" => "content_sniffing",
+            "--tls-cert" => "tls_cert",
+            "--tls-key" => "tls_key",
+            "--tls-sni" => "tls_sni",
+            "--redirect-http" => "redirect_http",
+            "--access-log" => "access_log",
+            "--access-log-format" => "access_log_format",
+            "--access-log-max-size" => "access_log_max_size",
+            "--access-log-keep" => "access_log_keep",
+            "--log-level" => "log_level",
+            "--log-timestamps" => "log_timestamps",
+            "--workers" => "workers",
+            "--queue-capacity" => "queue_capacity",
+            "--keep-alive-timeout" => "keep_alive_timeout",
+            "--watch-config" => "watch_config",
+            "--drain-timeout" => "drain_timeout",
+            "--max-requests" => "max_requests",
+            "--max-request-line" => "max_request_line",
+            "--max-header-size" => "max_header_size",
+            "--max-header-count" => "max_header_count",
+            "--max-body-size" => "max_body_size",
+            _ => return Err(format!("unknown argument {:?}, see --help", arg)),
+        };
+
+        let value = match inline_value {
+            Some(value) => value,
+            None => args
+                .next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", flag))?,
+        };
+        settings.push((key.to_string(), value));
+    }
+
+    Ok(settings)
+}
+
+// Parse "[::]:8443,root=/srv/site,tls_cert=cert.pem,tls_key=key.pem"
+fn parse_listen(entry: &str) -> Result<ListenConfig, String> {
+    let mut parts = entry.split(',').map(str::trim);
+    let mut listen = ListenConfig {
+        address: parts.next().unwrap_or("").to_string(),
+        pages_dir: None,
+        tls: false,
+        tls_cert: None,
+        tls_key: None,
+        mode: None,
+    };
+
+    for option in parts {
+        match option.split_once('=') {
+            None if option == "tls" => listen.tls = true,
+            Some(("root", dir)) => listen.pages_dir = Some(PathBuf::from(dir)),
+            Some(("tls_cert", file)) => {
+                listen.tls = true;
+                listen.tls_cert = Some(PathBuf::from(file));
+            }
+            Some(("tls_key", file)) => {
+                listen.tls = true;
+                listen.tls_key = Some(PathBuf::from(file));
+            }
+            Some(("mode", mode)) => {
+                let mode = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o777)
+                    .ok_or_else(|| format!("expected an octal mode like 660, got {:?}", mode))?;
+                listen.mode = Some(mode);
+            }
+            _ => return Err(format!("unknown listen option {:?} in {:?}", option, entry)),
+        }
+    }
+    Ok(listen)
+}
+
+// An address has to resolve to at least one socket address
+fn check_address(setting: &str, address: &str) -> Result<(), String> {
+    match address.to_socket_addrs() {
+        Ok(mut addrs) => match addrs.next() {
+            Some(_) => Ok(()),
+            None => Err(format!("{} address {:?} did not resolve", setting, address)),
+        },
+        Err(e) => Err(format!("invalid {} address {:?}: {}", setting, address, e)),
+    }
+}
+
+fn canonical_dir(dir: &Path) -> Result<PathBuf, String> {
+    fs::canonicalize(dir).map_err(|e| format!("cannot resolve pages directory {:?}: {}", dir, e))
+}
+
+// Parse "example.com=cert.pem,key.pem"
+fn parse_sni_certificate(entry: &str) -> Result<SniCertificate, String> {
+    let (hostname, files) = entry
+        .split_once('=')
+        .ok_or_else(|| format!("expected HOST=CERT,KEY, got {:?}", entry))?;
+    let (cert, key) = files
+        .split_once(',')
+        .ok_or_else(|| format!("expected HOST=CERT,KEY, got {:?}", entry))?;
+    Ok(SniCertificate {
+        hostname: hostname.trim().to_lowercase(),
+        cert: PathBuf::from(cert.trim()),
+        key: PathBuf::from(key.trim()),
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("expected a non-negative number, got {:?}", value))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected true or false, got {:?}", value)),
+    }
+}
+
+// Drop a trailing `# comment` that isn't inside a quoted string
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+// Fix exe file pathing
+fn get_pages_directory() -> PathBuf {
+    // First, try to find the pages directory next to the executable
+    if let Ok(exe_path) = env::current_exe() {
+        if let Some(exe_dir) = exe_path.parent() {
+            // Check if we're running from a development environment (target/debug)
+            let project_root = if exe_dir.ends_with("target/debug") || exe_dir.ends_with("target/release") {
+                exe_dir.parent().unwrap().parent().unwrap().to_path_buf()
+            } else {
+                exe_dir.to_path_buf()
+            };
+
+            let pages_dir = project_root.join("pages");
+            return pages_dir;
+        }
+    }
+
+    // Final fallback: current directory pages folder
+    env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join("pages")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn root_args() -> Vec<String> {
+        args(&["--root", env::temp_dir().to_str().unwrap()])
+    }
+
+    #[test]
+    fn unknown_environment_variables_are_ignored() {
+        let env_vars = vec![
+            ("SIMPLE_HTTP_UNRELATED".to_string(), "1".to_string()),
+            ("SIMPLE_HTTP_WORKERS".to_string(), "3".to_string()),
+        ];
+        let config = Config::from_sources(&root_args(), &env_vars).unwrap();
+        assert_eq!(config.workers, 3);
+        assert_eq!(config.unknown_env_vars, ["SIMPLE_HTTP_UNRELATED"]);
+    }
+
+    #[test]
+    fn bad_environment_values_are_errors() {
+        let env_vars = vec![("SIMPLE_HTTP_WORKERS".to_string(), "many".to_string())];
+        let error = Config::from_sources(&root_args(), &env_vars).unwrap_err();
+        assert!(error.contains("SIMPLE_HTTP_WORKERS"), "{}", error);
+    }
+
+    fn changed_names(config: &Config, old: &Config) -> Vec<&'static str> {
+        config.changes_from(old).into_iter().map(|(name, _, _)| name).collect()
+    }
+
+    #[test]
+    fn reload_keeps_restart_settings_and_applies_the_rest() {
+        let running = Config::default();
+        let mut reloaded = Config::default();
+        reloaded.set("bind", "0.0.0.0:9000").unwrap();
+        reloaded.set("keep_alive_timeout", "30").unwrap();
+
+        let changes = reloaded.changes_from(&running);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0], ("bind_address", "\"127.0.0.1:8080\"".to_string(), "\"0.0.0.0:9000\"".to_string()));
+        assert_eq!(changes[1].0, "keep_alive_timeout");
+        assert!(RESTART_SETTINGS.contains(&changes[0].0));
+        assert!(!RESTART_SETTINGS.contains(&changes[1].0));
+
+        reloaded.keep_restart_settings(&running);
+        assert_eq!(reloaded.bind_address, running.bind_address);
+        assert_eq!(reloaded.keep_alive_timeout, Duration::from_secs(30));
+        assert_eq!(changed_names(&reloaded, &running), ["keep_alive_timeout"]);
+    }
+
+    #[test]
+    fn mime_types_changes_are_detected() {
+        let path = env::temp_dir().join(format!("simple_http_config_mime_{}.types", std::process::id()));
+        let load = |text: &str| {
+            fs::write(&path, text).unwrap();
+            let mut config = Config::default();
+            config.set("mime_types", path.to_str().unwrap()).unwrap();
+            config
+        };
+        let many = "text/a a\ntext/b b\ntext/c c\ntext/d d\n";
+        let first = load(many);
+        let same = load(many);
+        let edited = load("text/a a\ntext/b b\ntext/c c\ntext/x d\n");
+        fs::remove_file(&path).unwrap();
+
+        assert!(changed_names(&same, &first).is_empty());
+        assert_eq!(changed_names(&edited, &first), ["mime_types"]);
+        assert_eq!(changed_names(&Config::default(), &first), ["mime_types"]);
+    }
+
+    fn listen_args(listen: &str) -> Vec<String> {
+        let mut args = root_args();
+        args.extend(["--listen".to_string(), listen.to_string()]);
+        args
+    }
+
+    #[test]
+    fn parses_listen_entries() {
+        let listen = parse_listen("[::]:8443, tls").unwrap();
+        assert_eq!(listen.address, "[::]:8443");
+        assert!(listen.tls);
+        assert_eq!(listen.pages_dir, None);
+
+        let listen = parse_listen("0.0.0.0:8080,root=/srv/other").unwrap();
+        assert!(!listen.tls);
+        assert_eq!(listen.pages_dir, Some(PathBuf::from("/srv/other")));
+
+        let listen = parse_listen("[::1]:443,tls_cert=a.pem,tls_key=a.key").unwrap();
+        assert!(listen.tls);
+        assert_eq!(listen.tls_cert, Some(PathBuf::from("a.pem")));
+        assert_eq!(listen.tls_key, Some(PathBuf::from("a.key")));
+
+        let listen = parse_listen("unix:/run/site.sock,mode=0o660").unwrap();
+        assert_eq!(listen.unix_path(), Some(Path::new("/run/site.sock")));
+        assert_eq!(listen.mode, Some(0o660));
+    }
+
+    #[test]
+    fn rejects_bad_listen_options() {
+        assert!(parse_listen("127.0.0.1:80,fast").unwrap_err().contains("unknown listen option"));
+        assert!(parse_listen("127.0.0.1:80,root").is_err());
+        assert!(parse_listen("unix:/run/site.sock,mode=999").is_err());
+    }
+
+    #[test]
+    fn several_listen_addresses() {
+        let config = Config::from_sources(&listen_args("127.0.0.1:8080; [::]:8443,tls_cert=c,tls_key=k"), &[]).unwrap();
+        let addresses: Vec<&str> = config.listen.iter().map(|listen| listen.address.as_str()).collect();
+        assert_eq!(addresses, ["127.0.0.1:8080", "[::]:8443"]);
+        assert!(config.tls_enabled());
+    }
+
+    #[test]
+    fn validates_listen_entries() {
+        let error = |listen: &str| Config::from_sources(&listen_args(listen), &[]).unwrap_err();
+        assert!(error("[::]:8443,tls_cert=c.pem").contains("set together"));
+        assert!(error("[::]:8443,tls").contains("no tls_cert"));
+        assert!(error("127.0.0.1:8080,mode=660").contains("mode only applies"));
+        assert!(error("127.0.0.1:8080,root=/does/not/exist").contains("does not exist"));
+        assert!(error("not an address").contains("invalid listen address"));
+    }
+
+    #[test]
+    fn unknown_file_keys_are_errors() {
+        let path = env::temp_dir().join(format!("simple_http_config_{}.toml", std::process::id()));
+        fs::write(&path, "unrelated = 1\n").unwrap();
+        let mut config = Config::default();
+        let result = config.apply_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.unwrap_err().contains("unknown setting"));
+    }
+}
@@ -0,0 +1,376 @@
+// Dependency-free DEFLATE encoder (RFC 1951) with gzip (RFC 1952) and zlib
+// (RFC 1950) wrappers. Matches are found with a hash-chained LZ77 search and
+// written with the fixed Huffman codes, which keeps the encoder small while
+// still shrinking typical HTML, CSS and JavaScript to well under half.
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// How many earlier positions to try per match, trading speed for ratio
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Compress `data` into a gzip member
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // Magic, deflate method, no flags, no mtime, no extra flags, unknown OS
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+// Compress `data` into a zlib stream, which is what "deflate" means in HTTP
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // 32K window, deflate method, default level, header checksum
+    let mut out = vec![0x78, 0x9c];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// Compress `data` into a raw DEFLATE stream made of one fixed-Huffman block
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes)
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let (length, distance) = find_match(data, pos, &head, &prev);
+
+        if length >= MIN_MATCH {
+            write_length(&mut writer, length);
+            write_distance(&mut writer, distance);
+            for i in pos..pos + length {
+                insert_hash(data, i, &mut head, &mut prev);
+            }
+            pos += length;
+        } else {
+            write_literal(&mut writer, data[pos] as u16);
+            insert_hash(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+
+    // End of block
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn insert_hash(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH > data.len() {
+        return;
+    }
+    let h = hash(data, pos);
+    prev[pos % WINDOW_SIZE] = head[h];
+    head[h] = pos;
+}
+
+// Longest earlier match for the bytes at `pos`, as (length, distance)
+fn find_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_length = MAX_MATCH.min(data.len() - pos);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(data, pos)];
+    let mut chain = 0;
+
+    while candidate != usize::MAX && chain < MAX_CHAIN {
+        let distance = pos - candidate;
+        if distance > WINDOW_SIZE {
+            break;
+        }
+
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[pos..pos + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best.0 {
+            best = (length, distance);
+            if length == max_length {
+                break;
+            }
+        }
+
+        // Older entries in this slot may have been overwritten by newer positions
+        let next = prev[candidate % WINDOW_SIZE];
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+        chain += 1;
+    }
+
+    best
+}
+
+// Literal/length symbols 0-287 with the fixed code lengths from RFC 1951 3.2.6
+fn write_literal(writer: &mut BitWriter, symbol: u16) {
+    let (code, bits) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xc0 + symbol - 280, 8),
+    };
+    writer.write_huffman(code as u32, bits);
+}
+
+fn write_length(writer: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+    write_literal(writer, 257 + index as u16);
+    writer.write_bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+}
+
+fn write_distance(writer: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+    // Fixed distance codes are plain 5-bit numbers
+    writer.write_huffman(index as u32, 5);
+    writer.write_bits((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32);
+}
+
+// Packs bits least-significant first, as DEFLATE requires
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            out: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are stored most-significant bit first
+    fn write_huffman(&mut self, code: u32, bits: u32) {
+        let reversed = code.reverse_bits() >> (32 - bits);
+        self.write_bits(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc = table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xffffffff
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal decoder for the fixed-Huffman blocks `deflate` writes
+    fn inflate(input: &[u8]) -> Vec<u8> {
+        let mut reader = BitReader { input, pos: 0 };
+        let mut out = Vec::new();
+        loop {
+            let last = reader.bits(1);
+            assert_eq!(reader.bits(2), 1, "expected a fixed Huffman block");
+            loop {
+                let symbol = reader.literal();
+                if symbol < 256 {
+                    out.push(symbol as u8);
+                    continue;
+                }
+                if symbol == 256 {
+                    break;
+                }
+                let index = (symbol - 257) as usize;
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32) as usize;
+                let index = reader.huffman(5) as usize;
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32) as usize;
+                assert!(distance <= out.len() && distance <= WINDOW_SIZE);
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+            if last == 1 {
+                return out;
+            }
+        }
+    }
+
+    struct BitReader<'a> {
+        input: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..count {
+                let bit = (self.input[self.pos / 8] >> (self.pos % 8)) & 1;
+                value |= (bit as u32) << i;
+                self.pos += 1;
+            }
+            value
+        }
+
+        fn huffman(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        fn literal(&mut self) -> u16 {
+            let code = self.huffman(7) as u16;
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bits(1) as u16;
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | self.bits(1) as u16) - 0x190,
+            }
+        }
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = deflate(data);
+        assert_eq!(inflate(&compressed), data);
+        compressed
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn adler32_check_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(round_trip(b""), [0x03, 0x00]);
+    }
+
+    #[test]
+    fn text_round_trips_and_shrinks() {
+        let html = "<li><a href=\"./page.html\">page</a></li>\n".repeat(200);
+        let compressed = round_trip(html.as_bytes());
+        assert!(compressed.len() < html.len() / 10);
+    }
+
+    #[test]
+    fn all_byte_values_round_trip() {
+        let data: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        round_trip(&data);
+    }
+
+    #[test]
+    fn long_runs_use_maximum_length_matches() {
+        for length in [257, 258, 259, 258 * 4 + 1, 10_000] {
+            round_trip(&vec![b'a'; length]);
+        }
+    }
+
+    #[test]
+    fn matches_near_the_window_limit() {
+        // Pseudo-random filler so the only matches are the deliberate repeats
+        let mut state = 12345u32;
+        let mut data: Vec<u8> = (0..40_000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        for distance in [WINDOW_SIZE - 1, WINDOW_SIZE, WINDOW_SIZE + 1] {
+            let start = data.len() - distance;
+            let repeat = data[start..start + 64].to_vec();
+            data.extend_from_slice(&repeat);
+        }
+        round_trip(&data);
+    }
+
+    #[test]
+    fn gzip_and_zlib_wrappers() {
+        let data = b"hello hello hello";
+
+        let gz = gzip(data);
+        assert_eq!(&gz[..3], [0x1f, 0x8b, 8]);
+        let trailer = &gz[gz.len() - 8..];
+        assert_eq!(trailer[..4], crc32(data).to_le_bytes());
+        assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+        assert_eq!(inflate(&gz[10..gz.len() - 8]), data);
+
+        let z = zlib(data);
+        assert_eq!(u16::from_be_bytes([z[0], z[1]]) % 31, 0);
+        assert_eq!(z[z.len() - 4..], adler32(data).to_be_bytes());
+        assert_eq!(inflate(&z[2..z.len() - 4]), data);
+    }
+}
@@ -0,0 +1,229 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Calendar fields of a UTC timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    // 1-12
+    pub month: u32,
+    // 1-31
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    // 0 = Thursday, matching 1970-01-01
+    weekday: usize,
+}
+
+impl DateTime {
+    // Break a time down into UTC calendar fields, whole seconds only
+    pub fn from_system_time(time: SystemTime) -> DateTime {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        };
+        let days = secs.div_euclid(86400);
+        let time_of_day = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (time_of_day / 3600) as u32,
+            minute: (time_of_day % 3600 / 60) as u32,
+            second: (time_of_day % 60) as u32,
+            weekday: days.rem_euclid(7) as usize,
+        }
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        MONTHS[self.month as usize - 1]
+    }
+}
+
+// Format a time as an IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT"
+pub fn format_http_date(time: SystemTime) -> String {
+    let date = DateTime::from_system_time(time);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAYS[date.weekday],
+        date.day,
+        date.month_name(),
+        date.year,
+        date.hour,
+        date.minute,
+        date.second
+    )
+}
+
+// Format a time as an RFC 3339 UTC timestamp, e.g. "1994-11-06T08:49:37Z"
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let date = DateTime::from_system_time(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        date.year, date.month, date.day, date.hour, date.minute, date.second
+    )
+}
+
+// Parse any of the three date formats HTTP recipients must accept:
+// IMF-fixdate, the obsolete RFC 850 format and asctime
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let (day, month, year, time) = match fields.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => (*day, *month, year.parse().ok()?, *time),
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut parts = date.split('-');
+            let day = parts.next()?;
+            let month = parts.next()?;
+            let year: i64 = parts.next()?.parse().ok()?;
+            let current_year = DateTime::from_system_time(SystemTime::now()).year;
+            (day, month, expand_two_digit_year(year, current_year), *time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (*day, *month, year.parse().ok()?, *time),
+        _ => return None,
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let mut clock = time.split(':').map(|part| part.parse::<u32>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if clock.next().is_some() || day == 0 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    if secs < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+// A two-digit year is in the current century unless that puts it more than
+// 50 years in the future, then it's in the previous one (RFC 9110 5.6.7)
+fn expand_two_digit_year(year: i64, current_year: i64) -> i64 {
+    let year = current_year - current_year.rem_euclid(100) + year;
+    if year > current_year + 50 {
+        year - 100
+    } else {
+        year
+    }
+}
+
+// Days since 1970-01-01 to (year, month, day), Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Inverse of civil_from_days
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sun, 06 Nov 1994 08:49:37 GMT
+    fn example() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(784111777)
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(format_http_date(example()), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_rfc3339(example()), "1994-11-06T08:49:37Z");
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn parses_all_three_formats() {
+        for value in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(parse_http_date(value), Some(example()), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn two_digit_years_slide_with_the_current_year() {
+        assert_eq!(expand_two_digit_year(70, 2026), 2070);
+        assert_eq!(expand_two_digit_year(76, 2026), 2076);
+        assert_eq!(expand_two_digit_year(77, 2026), 1977);
+        assert_eq!(expand_two_digit_year(0, 2026), 2000);
+        assert_eq!(expand_two_digit_year(99, 2026), 1999);
+        assert_eq!(expand_two_digit_year(49, 2099), 2049);
+        assert_eq!(expand_two_digit_year(99, 2099), 2099);
+        assert_eq!(expand_two_digit_year(94, 1999), 1994);
+    }
+
+    #[test]
+    fn rfc850_years_use_the_sliding_window() {
+        let now = DateTime::from_system_time(SystemTime::now()).year;
+        let next = (now + 1) % 100;
+        let parsed = parse_http_date(&format!("Friday, 01-Jan-{:02} 00:00:00 GMT", next)).unwrap();
+        assert_eq!(DateTime::from_system_time(parsed).year, now + 1);
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for value in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1994 08:49:37:00 GMT",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+        ] {
+            assert_eq!(parse_http_date(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn civil_conversion_round_trips() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(days_from_civil(2000, 2, 29), 11016);
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        for days in (-800_000..800_000).step_by(37) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn formatted_dates_parse_back() {
+        for secs in [0, 951782400, 1709164799, 4102444800] {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+        }
+    }
+}
@@ -0,0 +1,26 @@
+// A small static file HTTP server. The binary in main.rs wires these
+// modules together, other programs can embed the server through `server`
+// or build and inspect requests and responses directly.
+pub mod access_log;
+pub mod compression;
+pub mod config;
+pub mod deflate;
+pub mod httpdate;
+pub mod listing;
+pub mod log;
+pub mod mime;
+pub mod range;
+pub mod reload;
+pub mod request;
+pub mod request_target;
+pub mod response;
+pub mod sandbox;
+pub mod server;
+pub mod shutdown;
+pub mod sniff;
+pub mod thread_pool;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(unix)]
+pub mod unix_socket;
+pub mod validators;
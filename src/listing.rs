@@ -0,0 +1,232 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+    time::SystemTime,
+};
+use crate::{
+    httpdate::{format_rfc3339, DateTime},
+    request_target::percent_encode_path,
+};
+
+// One file or subdirectory shown in a directory listing
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    pub fn parse(value: &str) -> Option<SortKey> {
+        match value {
+            "name" => Some(SortKey::Name),
+            "size" => Some(SortKey::Size),
+            "modified" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        }
+    }
+}
+
+// Read the visible entries of a directory. Dotfiles are hidden, and so are
+// symlinks when `include_symlinks` is false.
+pub fn read_entries(dir: &Path, include_symlinks: bool) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for dir_entry in fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let name = match dir_entry.file_name().into_string() {
+            Ok(name) => name,
+            // Names that aren't UTF-8 can't be requested anyway
+            Err(_) => continue,
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        if !include_symlinks && dir_entry.file_type()?.is_symlink() {
+            continue;
+        }
+
+        // Follow symlinks for size and type, skipping broken ones
+        let metadata = match fs::metadata(dir_entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        entries.push(Entry {
+            name,
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(entries)
+}
+
+// Sort with directories first, then by the chosen key
+pub fn sort_entries(entries: &mut [Entry], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+        let ordering = if descending { ordering.reverse() } else { ordering };
+        b.is_dir.cmp(&a.is_dir).then(ordering)
+    });
+}
+
+// HTML index page for `url_path`, which must end in '/'. Column headings
+// link to the same page sorted by that column.
+pub fn render_html(url_path: &str, entries: &[Entry], key: SortKey, descending: bool) -> String {
+    let title = format!("Index of {}", escape_html(url_path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n    <meta charset=\"UTF-8\">\n    <title>{}</title>\n</head>\n<body>\n    <h1>{}</h1>\n    <table>\n        <tr>",
+        title, title
+    );
+
+    for (column, label) in [(SortKey::Name, "Name"), (SortKey::Size, "Size"), (SortKey::Modified, "Last modified")] {
+        // Clicking the current column flips the order
+        let order = if column == key && !descending { "desc" } else { "asc" };
+        html.push_str(&format!(
+            "<th><a href=\"?sort={}&amp;order={}\">{}</a></th>",
+            column.name(),
+            order,
+            label
+        ));
+    }
+    html.push_str("</tr>\n");
+
+    if url_path != "/" {
+        html.push_str("        <tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        // The "./" keeps names like "javascript:..." from being read as a URL scheme
+        let href = format!("./{}{}", percent_encode_path(&entry.name), suffix);
+        let size = if entry.is_dir { "-".to_string() } else { entry.size.to_string() };
+        html.push_str(&format!(
+            "        <tr><td><a href=\"{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&href),
+            escape_html(&entry.name),
+            suffix,
+            size,
+            entry.modified.map(format_listing_time).unwrap_or_default()
+        ));
+    }
+
+    html.push_str("    </table>\n</body>\n</html>\n");
+    html
+}
+
+// JSON listing: {"path": "/docs/", "entries": [{"name", "type", "size", "modified"}]}
+pub fn render_json(url_path: &str, entries: &[Entry]) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let modified = match entry.modified {
+                Some(time) => format!("\"{}\"", format_rfc3339(time)),
+                None => "null".to_string(),
+            };
+            format!(
+                "{{\"name\":\"{}\",\"type\":\"{}\",\"size\":{},\"modified\":{}}}",
+                escape_json(&entry.name),
+                if entry.is_dir { "directory" } else { "file" },
+                entry.size,
+                modified
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"path\":\"{}\",\"entries\":[{}]}}\n",
+        escape_json(url_path),
+        items.join(",")
+    )
+}
+
+fn format_listing_time(time: SystemTime) -> String {
+    let date = DateTime::from_system_time(time);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        date.year, date.month, date.day, date.hour, date.minute
+    )
+}
+
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, is_dir: bool) -> Entry {
+        Entry {
+            name: name.to_string(),
+            is_dir,
+            size: 0,
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn hrefs_are_relative_to_the_directory() {
+        let entries = [entry("javascript:alert(document.domain)", false), entry("sub dir", true)];
+        let html = render_html("/docs/", &entries, SortKey::Name, false);
+        assert!(html.contains("<a href=\"./javascript:alert(document.domain)\">"));
+        assert!(html.contains("<a href=\"./sub%20dir/\">"));
+        assert!(!html.contains("href=\"javascript:"));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let html = render_html("/", &[entry("<b>&\"", false)], SortKey::Name, false);
+        assert!(html.contains("&lt;b&gt;&amp;&quot;"));
+        assert!(!html.contains("<b>"));
+    }
+}
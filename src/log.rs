@@ -0,0 +1,235 @@
+// Leveled diagnostic logging. Messages go through the error!, warn!, info!,
+// debug! and trace! macros and are filtered per module, e.g. with the spec
+// "info,server=debug" everything logs at info except server, which also
+// logs debug. Errors and warnings go to stderr, the rest to stdout.
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::RwLock,
+    time::SystemTime,
+};
+use crate::httpdate::format_rfc3339;
+
+// Severity of a message, or as a filter the most verbose severity shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    // Only used in filters, shows nothing
+    Off,
+    Error,
+    Warn,
+    Info,
+    // Includes the request and response header dumps
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Level> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            // Names from the older quiet/normal/verbose setting
+            "quiet" => Some(Level::Error),
+            "normal" => Some(Level::Info),
+            "verbose" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+// Default level plus per-module overrides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    pub default: Level,
+    // (module, level), module names without the crate prefix, e.g. "server"
+    pub modules: Vec<(String, Level)>,
+}
+
+impl LogFilter {
+    pub const fn new(default: Level) -> LogFilter {
+        LogFilter {
+            default,
+            modules: Vec::new(),
+        }
+    }
+
+    // Parse "info", "server=debug" or "warn,server=trace,thread_pool=off"
+    pub fn parse(spec: &str) -> Result<LogFilter, String> {
+        let mut filter = LogFilter::new(Level::Info);
+        for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.split_once('=') {
+                Some((module, level)) => {
+                    let level = Level::parse(level).ok_or_else(|| format!("unknown log level {:?}", level))?;
+                    filter.modules.push((module.trim().to_string(), level));
+                }
+                None => {
+                    filter.default = Level::parse(part).ok_or_else(|| format!("unknown log level {:?}", part))?;
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    // Level for a module, the longest matching override wins
+    pub fn level_for(&self, module: &str) -> Level {
+        let module = short_module(module);
+        self.modules
+            .iter()
+            .filter(|(name, _)| {
+                module == name || module.strip_prefix(name.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+}
+
+struct Settings {
+    filter: LogFilter,
+    timestamps: bool,
+}
+
+// Until configure() runs, info and above are shown with timestamps
+static SETTINGS: RwLock<Settings> = RwLock::new(Settings {
+    filter: LogFilter::new(Level::Info),
+    timestamps: true,
+});
+
+// Replace the active filter, safe to call again while the server runs
+pub fn configure(filter: LogFilter, timestamps: bool) {
+    let mut settings = SETTINGS.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *settings = Settings { filter, timestamps };
+}
+
+// Whether a message at `level` from `module` would be written
+pub fn enabled(level: Level, module: &str) -> bool {
+    let settings = SETTINGS.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    level != Level::Off && level <= settings.filter.level_for(module)
+}
+
+// Write one message, used by the macros after checking enabled()
+pub fn write(level: Level, module: &str, args: fmt::Arguments) {
+    let timestamps = SETTINGS.read().map(|settings| settings.timestamps).unwrap_or(true);
+
+    let mut line = String::new();
+    if timestamps {
+        line.push_str(&format_rfc3339(SystemTime::now()));
+        line.push(' ');
+    }
+    line.push_str(&format!("{:<5} [{}] {}\n", level.name(), short_module(module), args));
+
+    // A closed terminal shouldn't take the server down, so write errors are ignored
+    let _ = if level <= Level::Warn {
+        io::stderr().lock().write_all(line.as_bytes())
+    } else {
+        io::stdout().lock().write_all(line.as_bytes())
+    };
+}
+
+// "simple_http_server::server" -> "server", the crate root (main) -> "main"
+fn short_module(module: &str) -> &str {
+    match module.split_once("::") {
+        Some((_, rest)) => rest,
+        None => "main",
+    }
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level, module_path!()) {
+            $crate::log::write($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_default_and_module_levels() {
+        let filter = LogFilter::parse(" warn , server=trace,thread_pool=off ").unwrap();
+        assert_eq!(filter.default, Level::Warn);
+        assert_eq!(
+            filter.modules,
+            [("server".to_string(), Level::Trace), ("thread_pool".to_string(), Level::Off)]
+        );
+        assert_eq!(LogFilter::parse("").unwrap(), LogFilter::new(Level::Info));
+    }
+
+    #[test]
+    fn legacy_level_names() {
+        assert_eq!(LogFilter::parse("quiet").unwrap().default, Level::Error);
+        assert_eq!(LogFilter::parse("normal").unwrap().default, Level::Info);
+        assert_eq!(LogFilter::parse("Verbose").unwrap().default, Level::Debug);
+        assert_eq!(LogFilter::parse("warning").unwrap().default, Level::Warn);
+    }
+
+    #[test]
+    fn unknown_levels_are_errors() {
+        assert!(LogFilter::parse("loud").is_err());
+        assert!(LogFilter::parse("info,server=loud").is_err());
+    }
+
+    #[test]
+    fn longest_matching_module_wins() {
+        let filter = LogFilter::parse("info,server=debug,server::tls=error,serve=off").unwrap();
+        assert_eq!(filter.level_for("simple_http_server::server"), Level::Debug);
+        assert_eq!(filter.level_for("simple_http_server::server::tls"), Level::Error);
+        assert_eq!(filter.level_for("simple_http_server::server::tls::sni"), Level::Error);
+        assert_eq!(filter.level_for("simple_http_server::server::other"), Level::Debug);
+        // A prefix only matches whole path segments
+        assert_eq!(filter.level_for("simple_http_server::server_extra"), Level::Info);
+        assert_eq!(filter.level_for("simple_http_server::serve"), Level::Off);
+        assert_eq!(filter.level_for("simple_http_server"), Level::Info);
+    }
+
+    #[test]
+    fn off_and_crate_root() {
+        let filter = LogFilter::parse("off,main=warn").unwrap();
+        assert_eq!(filter.level_for("simple_http_server::config"), Level::Off);
+        assert_eq!(filter.level_for("simple_http_server"), Level::Warn);
+        assert!(Level::Off < Level::Error && Level::Debug < Level::Trace);
+    }
+}
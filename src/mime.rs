@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Returned when no extension matches
+pub const DEFAULT_TYPE: &str = "application/octet-stream";
+
+// Built-in extension table, extensions are lowercase without the dot
+const BUILTIN_TYPES: &[(&str, &str)] = &[
+    // Documents and text
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("txt", "text/plain"),
+    ("text", "text/plain"),
+    ("csv", "text/csv"),
+    ("md", "text/markdown"),
+    ("markdown", "text/markdown"),
+    ("ics", "text/calendar"),
+    ("vtt", "text/vtt"),
+    ("xml", "application/xml"),
+    ("xhtml", "application/xhtml+xml"),
+    ("rss", "application/rss+xml"),
+    ("atom", "application/atom+xml"),
+    ("pdf", "application/pdf"),
+    ("rtf", "application/rtf"),
+    // Scripts and data
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("cjs", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("webmanifest", "application/manifest+json"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("wasm", "application/wasm"),
+    // Images
+    ("png", "image/png"),
+    ("apng", "image/apng"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("jfif", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("svgz", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("cur", "image/x-icon"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    // Fonts
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    // Audio
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("flac", "audio/flac"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("weba", "audio/webm"),
+    ("mid", "audio/midi"),
+    ("midi", "audio/midi"),
+    // Video
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    ("avi", "video/x-msvideo"),
+    ("mpeg", "video/mpeg"),
+    ("mpg", "video/mpeg"),
+    ("ts", "video/mp2t"),
+    ("m3u8", "application/vnd.apple.mpegurl"),
+    // Archives and binaries
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tgz", "application/gzip"),
+    ("br", "application/x-brotli"),
+    ("tar", "application/x-tar"),
+    ("bz2", "application/x-bzip2"),
+    ("xz", "application/x-xz"),
+    ("7z", "application/x-7z-compressed"),
+    ("rar", "application/vnd.rar"),
+    ("jar", "application/java-archive"),
+    ("apk", "application/vnd.android.package-archive"),
+    ("exe", "application/vnd.microsoft.portable-executable"),
+    ("dmg", "application/x-apple-diskimage"),
+    ("iso", "application/x-iso9660-image"),
+    ("bin", "application/octet-stream"),
+    // Office formats
+    ("doc", "application/msword"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("xls", "application/vnd.ms-excel"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("epub", "application/epub+zip"),
+];
+
+// Extension to content type lookup, the built-in table plus user overrides
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MimeTypes {
+    overrides: HashMap<String, String>,
+    // Files the overrides came from, in load order
+    files: Vec<PathBuf>,
+}
+
+impl MimeTypes {
+    // Load overrides from a file in the Apache/nginx mime.types format:
+    // "type ext1 ext2 ..." per line, with # comments. nginx's
+    // `types { ... }` wrapper and trailing semicolons are tolerated.
+    pub fn load_overrides(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read MIME types file {:?}: {}", path, e))?;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim().trim_end_matches(';');
+            if line.is_empty() || line == "types {" || line == "}" {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let mime_type = fields.next().unwrap_or("");
+            if !mime_type.contains('/') {
+                return Err(format!("{:?} line {}: expected a MIME type, got {:?}", path, number + 1, mime_type));
+            }
+            for extension in fields {
+                self.overrides.insert(
+                    extension.trim_start_matches('.').to_lowercase(),
+                    mime_type.to_string(),
+                );
+            }
+        }
+
+        self.files.push(path.to_path_buf());
+        Ok(())
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    // Number of extensions mapped by override files
+    pub fn override_count(&self) -> usize {
+        self.overrides.len()
+    }
+
+    // Content-Type for a file name, with a UTF-8 charset on text types.
+    // None when the extension is missing or unknown.
+    pub fn lookup(&self, filename: &str) -> Option<String> {
+        let extension = extension(filename)?;
+        let mime_type = self
+            .overrides
+            .get(&extension)
+            .map(String::as_str)
+            .or_else(|| builtin_type(&extension))?;
+        Some(with_charset(mime_type))
+    }
+}
+
+fn builtin_type(extension: &str) -> Option<&'static str> {
+    BUILTIN_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+// Lowercased extension of the last path segment, None for "README" or ".hidden"
+fn extension(filename: &str) -> Option<String> {
+    let name = filename.rsplit('/').next().unwrap_or(filename);
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => Some(extension.to_lowercase()),
+        _ => None,
+    }
+}
+
+// Browsers guess the charset of text without one, so state it
+pub fn with_charset(mime_type: &str) -> String {
+    if mime_type.starts_with("text/") && !mime_type.contains(';') {
+        format!("{}; charset=utf-8", mime_type)
+    } else {
+        mime_type.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_lookup() {
+        let types = MimeTypes::default();
+        assert_eq!(types.lookup("index.HTML").as_deref(), Some("text/html; charset=utf-8"));
+        assert_eq!(types.lookup("/docs/v1.2/readme"), None);
+        assert_eq!(types.lookup(".hidden"), None);
+        assert_eq!(types.lookup("archive.unknownext"), None);
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let path = std::env::temp_dir().join(format!("simple_http_mime_{}.types", std::process::id()));
+        fs::write(&path, "types {\n    text/x-custom  html  mine; # comment\n}\n").unwrap();
+        let mut types = MimeTypes::default();
+        let result = types.load_overrides(&path);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(types.override_count(), 2);
+        assert_eq!(types.lookup("a.mine").as_deref(), Some("text/x-custom; charset=utf-8"));
+        assert_eq!(types.lookup("a.html").as_deref(), Some("text/x-custom; charset=utf-8"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    fs::File,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use crate::response::Body;
+
+// Requests asking for more ranges than this get the whole file instead
+const MAX_RANGES: usize = 16;
+
+// An inclusive byte range within a representation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    // Value for a Content-Range header, e.g. "bytes 0-99/1000"
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeError {
+    // The header isn't a byte range we understand, serve the whole file (200)
+    Ignored,
+    // None of the ranges overlap the file (416)
+    Unsatisfiable,
+}
+
+// Parse a Range header such as "bytes=0-99,200-,-50" for a representation of
+// `total` bytes. Overlapping or adjacent ranges are merged.
+pub fn parse_range_header(header: &str, total: u64) -> Result<Vec<ByteRange>, RangeError> {
+    let spec = header
+        .trim()
+        .strip_prefix("bytes=")
+        .ok_or(RangeError::Ignored)?;
+
+    let mut ranges = Vec::new();
+    let mut any_valid_syntax = false;
+    for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let (first, last) = part.split_once('-').ok_or(RangeError::Ignored)?;
+        let range = match (first.trim(), last.trim()) {
+            // "-500": the last 500 bytes
+            ("", suffix) => {
+                let suffix: u64 = suffix.parse().map_err(|_| RangeError::Ignored)?;
+                any_valid_syntax = true;
+                if suffix == 0 || total == 0 {
+                    continue;
+                }
+                ByteRange {
+                    start: total.saturating_sub(suffix),
+                    end: total - 1,
+                }
+            }
+            // "500-" or "500-999"
+            (first, last) => {
+                let start: u64 = first.parse().map_err(|_| RangeError::Ignored)?;
+                let end = if last.is_empty() {
+                    u64::MAX
+                } else {
+                    last.parse().map_err(|_| RangeError::Ignored)?
+                };
+                if end < start {
+                    return Err(RangeError::Ignored);
+                }
+                any_valid_syntax = true;
+                if start >= total {
+                    continue;
+                }
+                ByteRange {
+                    start,
+                    end: end.min(total - 1),
+                }
+            }
+        };
+        ranges.push(range);
+    }
+
+    if !any_valid_syntax {
+        return Err(RangeError::Ignored);
+    }
+    if ranges.is_empty() {
+        return Err(RangeError::Unsatisfiable);
+    }
+    if ranges.len() > MAX_RANGES {
+        return Err(RangeError::Ignored);
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    Ok(merged)
+}
+
+// Separator for multipart/byteranges bodies, unique enough not to appear in the data
+pub fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos())
+        .unwrap_or(0);
+    format!("byteranges_{:032x}", nanos)
+}
+
+// Build a multipart/byteranges body with one part per range of `file`,
+// streaming each range from disk
+pub fn multipart_body(file: Arc<File>, total: u64, ranges: &[ByteRange], content_type: &str, boundary: &str) -> Body {
+    let mut parts = Vec::new();
+    for range in ranges {
+        let part_head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            content_type,
+            range.content_range(total)
+        );
+        parts.push(Body::Bytes(part_head.into_bytes()));
+        parts.push(Body::File {
+            file: Arc::clone(&file),
+            offset: range.start,
+            length: range.end - range.start + 1,
+        });
+    }
+    parts.push(Body::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+    Body::Multi(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(header: &str, total: u64) -> Vec<(u64, u64)> {
+        parse_range_header(header, total)
+            .unwrap()
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(ranges("bytes=0-99", 1000), [(0, 99)]);
+        assert_eq!(ranges("bytes=900-", 1000), [(900, 999)]);
+        assert_eq!(ranges("bytes=900-5000", 1000), [(900, 999)]);
+        assert_eq!(ranges(" bytes= 5 - 5 ", 1000), [(5, 5)]);
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(ranges("bytes=-100", 1000), [(900, 999)]);
+        assert_eq!(ranges("bytes=-5000", 1000), [(0, 999)]);
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_merge() {
+        assert_eq!(ranges("bytes=0-9,10-19,5-7", 100), [(0, 19)]);
+        assert_eq!(ranges("bytes=50-59,0-9,-10", 100), [(0, 9), (50, 59), (90, 99)]);
+        assert_eq!(ranges("bytes=0-10,20-,-90", 100), [(0, 99)]);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_dropped() {
+        assert_eq!(ranges("bytes=0-9,1000-2000,-0", 100), [(0, 9)]);
+        assert_eq!(parse_range_header("bytes=100-", 100), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse_range_header("bytes=-0", 100), Err(RangeError::Unsatisfiable));
+    }
+
+    #[test]
+    fn zero_length_file() {
+        assert_eq!(parse_range_header("bytes=0-", 0), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse_range_header("bytes=-10", 0), Err(RangeError::Unsatisfiable));
+    }
+
+    #[test]
+    fn malformed_headers_are_ignored() {
+        for header in ["items=0-1", "bytes=", "bytes=5", "bytes=a-b", "bytes=9-0", "bytes=0-1,x", "bytes=--1"] {
+            assert_eq!(parse_range_header(header, 100), Err(RangeError::Ignored), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn too_many_ranges_are_ignored() {
+        let list = |count: u64| {
+            let parts: Vec<String> = (0..count).map(|i| format!("{}-{}", i * 10, i * 10 + 1)).collect();
+            format!("bytes={}", parts.join(","))
+        };
+        assert_eq!(ranges(&list(MAX_RANGES as u64), 1000).len(), MAX_RANGES);
+        assert_eq!(parse_range_header(&list(MAX_RANGES as u64 + 1), 1000), Err(RangeError::Ignored));
+    }
+
+    #[test]
+    fn content_range_value() {
+        assert_eq!(ByteRange { start: 0, end: 99 }.content_range(1000), "bytes 0-99/1000");
+    }
+}
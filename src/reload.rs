@@ -0,0 +1,75 @@
+// Configuration reloads, requested by SIGHUP, by code embedding the server
+// or by the config file changing on disk when watch_config is on. The
+// reload itself happens in server::serve, which swaps in the new settings
+// for connections accepted from then on.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+use crate::config::Config;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// How often watched files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[cfg(unix)]
+extern "C" fn on_sighup(_signum: std::os::raw::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Reload the configuration on SIGHUP. Does nothing off Unix.
+pub fn install_handler() {
+    #[cfg(unix)]
+    unsafe {
+        crate::shutdown::sys::signal(crate::shutdown::sys::SIGHUP, on_sighup);
+    }
+}
+
+// Ask a running server to reload its configuration, as if it had received SIGHUP
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Whether a reload was requested since the last call, clearing the request
+pub fn take_requested() -> bool {
+    REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+// Polls the modification times of the files a configuration was read from
+pub struct Watcher {
+    enabled: bool,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant,
+}
+
+impl Watcher {
+    pub fn new(config: &Config) -> Watcher {
+        let files = config
+            .config_file
+            .iter()
+            .chain(config.mime_types.files())
+            .map(|path| (path.clone(), modified(path)))
+            .collect();
+        Watcher {
+            enabled: config.watch_config,
+            files,
+            last_check: Instant::now(),
+        }
+    }
+
+    // True once any watched file has changed, checked at most every WATCH_INTERVAL
+    pub fn changed(&mut self) -> bool {
+        if !self.enabled || self.last_check.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        self.files.iter().any(|(path, seen)| modified(path) != *seen)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
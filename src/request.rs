@@ -0,0 +1,441 @@
+use std::io::{self, BufRead, ErrorKind};
+use crate::request_target::RequestTarget;
+
+// Size limits applied while reading a request off the wire
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    // Longest request line accepted, longer ones get a 414
+    pub max_request_line: usize,
+    // Total bytes of header lines accepted, more gets a 431
+    pub max_header_size: usize,
+    // Number of header lines accepted, more gets a 431
+    pub max_header_count: usize,
+    // Largest Content-Length accepted, larger gets a 413
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_request_line: 8192,
+            max_header_size: 16384,
+            max_header_count: 100,
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+// Why a request could not be read
+#[derive(Debug)]
+pub enum ParseError {
+    // The connection failed or closed part way through, nothing can be sent back
+    Io(io::Error),
+    // 400, with a description of what was wrong
+    BadRequest(String),
+    // 413
+    PayloadTooLarge,
+    // 414
+    UriTooLong,
+    // 431
+    HeaderFieldsTooLarge,
+    // 501, e.g. an unsupported Transfer-Encoding
+    NotImplemented(String),
+    // 505
+    VersionNotSupported(String),
+}
+
+impl ParseError {
+    // Status code to answer with, None when the connection is already unusable
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ParseError::Io(_) => None,
+            ParseError::BadRequest(_) => Some(400),
+            ParseError::PayloadTooLarge => Some(413),
+            ParseError::UriTooLong => Some(414),
+            ParseError::HeaderFieldsTooLarge => Some(431),
+            ParseError::NotImplemented(_) => Some(501),
+            ParseError::VersionNotSupported(_) => Some(505),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "connection error: {}", e),
+            ParseError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            ParseError::PayloadTooLarge => write!(f, "request body too large"),
+            ParseError::UriTooLong => write!(f, "request line too long"),
+            ParseError::HeaderFieldsTooLarge => write!(f, "request headers too large"),
+            ParseError::NotImplemented(reason) => write!(f, "not implemented: {}", reason),
+            ParseError::VersionNotSupported(version) => write!(f, "unsupported HTTP version {:?}", version),
+        }
+    }
+}
+
+// A parsed HTTP request
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub target: RequestTarget,
+    pub version: String,
+    // Header names keep the case the client sent, use `header` to look them up.
+    // Repeated headers are folded into one comma-separated value.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    // Build a request from its head: the request line followed by header lines
+    pub fn parse_head(lines: &[String]) -> Result<Request, ParseError> {
+        let request_line = lines
+            .first()
+            .ok_or_else(|| ParseError::BadRequest("empty request".to_string()))?;
+        let parts: Vec<&str> = request_line.split(' ').collect();
+        if parts.len() != 3 || parts.iter().any(|part| part.is_empty()) {
+            return Err(ParseError::BadRequest(format!("malformed request line {:?}", request_line)));
+        }
+
+        let method = parts[0];
+        if !method.bytes().all(is_token_byte) {
+            return Err(ParseError::BadRequest(format!("invalid method {:?}", method)));
+        }
+
+        let version = parts[2];
+        check_version(version)?;
+
+        let target = RequestTarget::parse(parts[1]).map_err(ParseError::BadRequest)?;
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in &lines[1..] {
+            // Obsolete line folding: a continuation line extends the previous value
+            if line.starts_with([' ', '\t']) {
+                let (_, value) = headers
+                    .last_mut()
+                    .ok_or_else(|| ParseError::BadRequest("continuation line before any header".to_string()))?;
+                value.push(' ');
+                value.push_str(line.trim());
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| ParseError::BadRequest(format!("malformed header line {:?}", line)))?;
+            if name.is_empty() || !name.bytes().all(is_token_byte) {
+                return Err(ParseError::BadRequest(format!("invalid header name {:?}", name)));
+            }
+
+            let value = value.trim();
+            match headers.iter_mut().find(|(header, _)| header.eq_ignore_ascii_case(name)) {
+                Some((_, existing)) => {
+                    existing.push_str(", ");
+                    existing.push_str(value);
+                }
+                None => headers.push((name.to_string(), value.to_string())),
+            }
+        }
+
+        Ok(Request {
+            method: method.to_string(),
+            target,
+            version: version.to_string(),
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    // First value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Length of the body announced by Content-Length, zero when absent
+    pub fn content_length(&self) -> Result<usize, ParseError> {
+        match self.header("Content-Length") {
+            Some(value) => value
+                .parse()
+                .map_err(|_| ParseError::BadRequest(format!("invalid Content-Length {:?}", value))),
+            None => Ok(0),
+        }
+    }
+
+    // HTTP/1.1 connections persist unless the client asks to close,
+    // HTTP/1.0 connections close unless the client asks for keep-alive
+    pub fn wants_keep_alive(&self) -> bool {
+        match self.header("Connection").map(str::to_lowercase) {
+            Some(value) if value.contains("close") => false,
+            Some(value) if value.contains("keep-alive") => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+}
+
+// Read one request, head and body, from the connection. Returns Ok(None) when
+// the client closed or went idle before sending anything.
+pub fn read_request<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Option<Request>, ParseError> {
+    // Ignore stray blank lines between pipelined requests
+    let request_line = loop {
+        match read_line(reader, limits.max_request_line) {
+            Ok(Some(line)) if line.is_empty() => continue,
+            Ok(Some(line)) => break line,
+            Ok(None) => return Ok(None),
+            Err(LineError::TooLong) => return Err(ParseError::UriTooLong),
+            // A timeout or reset between requests is just the end of the connection
+            Err(LineError::Io(_)) => return Ok(None),
+            Err(LineError::Incomplete) => return Err(incomplete()),
+        }
+    };
+
+    let mut lines = vec![into_string(request_line)?];
+    let mut header_size = 0;
+    loop {
+        let remaining = limits.max_header_size.saturating_sub(header_size);
+        let line = match read_line(reader, remaining) {
+            Ok(Some(line)) => line,
+            Ok(None) | Err(LineError::Incomplete) => return Err(incomplete()),
+            Err(LineError::TooLong) => return Err(ParseError::HeaderFieldsTooLarge),
+            Err(LineError::Io(e)) => return Err(ParseError::Io(e)),
+        };
+        if line.is_empty() {
+            break;
+        }
+
+        header_size += line.len();
+        // `lines` starts with the request line, so this lets exactly max_header_count through
+        if lines.len() > limits.max_header_count {
+            return Err(ParseError::HeaderFieldsTooLarge);
+        }
+        lines.push(into_string(line)?);
+    }
+
+    let mut request = Request::parse_head(&lines)?;
+
+    // Read any body so the next request on this connection starts in the right place
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(ParseError::NotImplemented("Transfer-Encoding is not supported".to_string()));
+    }
+    let length = request.content_length()?;
+    if length > limits.max_body_size {
+        return Err(ParseError::PayloadTooLarge);
+    }
+    if length > 0 {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).map_err(ParseError::Io)?;
+        request.body = body;
+    }
+
+    Ok(Some(request))
+}
+
+enum LineError {
+    TooLong,
+    Incomplete,
+    Io(io::Error),
+}
+
+// Read a line ending in LF or CRLF without the line ending, refusing lines
+// longer than `max` bytes. Ok(None) means end of stream before any byte.
+fn read_line<R: BufRead>(reader: &mut R, max: usize) -> Result<Option<Vec<u8>>, LineError> {
+    let mut line = Vec::new();
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(LineError::Io(e)),
+        };
+        if available.is_empty() {
+            return if line.is_empty() { Ok(None) } else { Err(LineError::Incomplete) };
+        }
+
+        let (chunk, found_newline) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..=i], true),
+            None => (available, false),
+        };
+        let used = chunk.len();
+        line.extend_from_slice(chunk);
+        reader.consume(used);
+
+        if found_newline {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if line.len() > max {
+                return Err(LineError::TooLong);
+            }
+            return Ok(Some(line));
+        }
+
+        // Leave room for a trailing CR that will be stripped
+        if line.len() > max + 1 {
+            return Err(LineError::TooLong);
+        }
+    }
+}
+
+fn into_string(line: Vec<u8>) -> Result<String, ParseError> {
+    String::from_utf8(line).map_err(|_| ParseError::BadRequest("request head is not valid UTF-8".to_string()))
+}
+
+fn incomplete() -> ParseError {
+    ParseError::BadRequest("connection closed in the middle of the request".to_string())
+}
+
+// Accept HTTP/1.x, answer other well-formed versions with 505
+fn check_version(version: &str) -> Result<(), ParseError> {
+    let number = version
+        .strip_prefix("HTTP/")
+        .ok_or_else(|| ParseError::BadRequest(format!("malformed HTTP version {:?}", version)))?;
+    let (major, minor) = number
+        .split_once('.')
+        .filter(|(major, minor)| {
+            !major.is_empty()
+                && !minor.is_empty()
+                && major.bytes().all(|b| b.is_ascii_digit())
+                && minor.bytes().all(|b| b.is_ascii_digit())
+        })
+        .ok_or_else(|| ParseError::BadRequest(format!("malformed HTTP version {:?}", version)))?;
+
+    match (major, minor) {
+        ("1", "0") | ("1", "1") => Ok(()),
+        _ => Err(ParseError::VersionNotSupported(version.to_string())),
+    }
+}
+
+// Characters allowed in methods and header names (RFC 9110 token)
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &str) -> Result<Option<Request>, ParseError> {
+        read_with(input, &Limits::default())
+    }
+
+    fn read_with(input: &str, limits: &Limits) -> Result<Option<Request>, ParseError> {
+        read_request(&mut input.as_bytes(), limits)
+    }
+
+    fn status(input: &str, limits: &Limits) -> Option<u16> {
+        read_with(input, limits).unwrap_err().status()
+    }
+
+    #[test]
+    fn crlf_and_lf_line_endings() {
+        for input in ["GET /a HTTP/1.1\r\nHost: x\r\n\r\n", "GET /a HTTP/1.1\nHost: x\n\n"] {
+            let request = read(input).unwrap().unwrap();
+            assert_eq!(request.method, "GET");
+            assert_eq!(request.target.path, "/a");
+            assert_eq!(request.header("host"), Some("x"));
+        }
+    }
+
+    #[test]
+    fn blank_lines_before_the_request_are_skipped() {
+        let request = read("\r\n\r\nGET / HTTP/1.0\r\n\r\n").unwrap().unwrap();
+        assert_eq!(request.version, "HTTP/1.0");
+    }
+
+    #[test]
+    fn closed_or_empty_connection_is_not_an_error() {
+        assert!(read("").unwrap().is_none());
+        assert!(read("\r\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn repeated_and_folded_headers() {
+        let input = "GET / HTTP/1.1\r\nAccept: a\r\nX-Long: one\r\n  two\r\naccept: b\r\n\r\n";
+        let request = read(input).unwrap().unwrap();
+        assert_eq!(request.header("Accept"), Some("a, b"));
+        assert_eq!(request.header("X-Long"), Some("one two"));
+    }
+
+    #[test]
+    fn body_is_read_and_the_next_request_follows() {
+        let mut input = "POST /f HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET /next HTTP/1.1\r\n\r\n".as_bytes();
+        let first = read_request(&mut input, &Limits::default()).unwrap().unwrap();
+        assert_eq!(first.body, b"hello");
+        let second = read_request(&mut input, &Limits::default()).unwrap().unwrap();
+        assert_eq!(second.target.path, "/next");
+    }
+
+    #[test]
+    fn truncated_body_is_an_io_error() {
+        let result = read("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort");
+        assert!(matches!(result, Err(ParseError::Io(_))));
+    }
+
+    #[test]
+    fn request_line_limit() {
+        let limits = Limits { max_request_line: 17, ..Limits::default() };
+        assert!(read_with("GET /abc HTTP/1.1\r\n\r\n", &limits).is_ok());
+        assert_eq!(status("GET /abcd HTTP/1.1\r\n\r\n", &limits), Some(414));
+    }
+
+    #[test]
+    fn header_size_limit() {
+        let limits = Limits { max_header_size: 10, ..Limits::default() };
+        assert!(read_with("GET / HTTP/1.1\r\nA: 1234567\r\n\r\n", &limits).is_ok());
+        assert_eq!(status("GET / HTTP/1.1\r\nA: 12345678\r\n\r\n", &limits), Some(431));
+    }
+
+    #[test]
+    fn header_count_limit_allows_exactly_the_maximum() {
+        let limits = Limits { max_header_count: 3, ..Limits::default() };
+        let request = read_with("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n", &limits).unwrap().unwrap();
+        assert_eq!(request.headers.len(), 3);
+        assert_eq!(status("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n", &limits), Some(431));
+    }
+
+    #[test]
+    fn body_size_limit() {
+        let limits = Limits { max_body_size: 4, ..Limits::default() };
+        assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello", &limits), Some(413));
+    }
+
+    #[test]
+    fn versions() {
+        let limits = Limits::default();
+        assert_eq!(status("GET / HTTP/2.0\r\n\r\n", &limits), Some(505));
+        assert_eq!(status("GET / HTTP/1.x\r\n\r\n", &limits), Some(400));
+        assert_eq!(status("GET / FTP/1.1\r\n\r\n", &limits), Some(400));
+    }
+
+    #[test]
+    fn malformed_requests_are_bad_requests() {
+        let limits = Limits::default();
+        for input in [
+            "GET /\r\n\r\n",
+            "GET  / HTTP/1.1\r\n\r\n",
+            "G(T / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nNo colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nBad Name: x\r\n\r\n",
+            "GET / HTTP/1.1\r\n folded first\r\n\r\n",
+            "GET / HTTP/1.1\r\nContent-Length: ten\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\n",
+        ] {
+            assert_eq!(status(input, &limits), Some(400), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn transfer_encoding_is_not_implemented() {
+        let input = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert_eq!(status(input, &Limits::default()), Some(501));
+    }
+
+    #[test]
+    fn keep_alive_defaults_follow_the_version() {
+        let keep_alive = |input: &str| read(input).unwrap().unwrap().wants_keep_alive();
+        assert!(keep_alive("GET / HTTP/1.1\r\n\r\n"));
+        assert!(!keep_alive("GET / HTTP/1.1\r\nConnection: close\r\n\r\n"));
+        assert!(!keep_alive("GET / HTTP/1.0\r\n\r\n"));
+        assert!(keep_alive("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n"));
+    }
+}
@@ -0,0 +1,214 @@
+// Parsed form of the request-target from the request line, e.g.
+// "/docs/my%20page.html?lang=en#top"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestTarget {
+    // The target exactly as it appeared in the request line
+    pub raw: String,
+    // Percent-decoded path, always starting with '/' except for the "*" target
+    pub path: String,
+    // Raw query string without the leading '?'
+    pub query: Option<String>,
+    // Decoded query parameters in the order they appeared
+    pub query_params: Vec<(String, String)>,
+}
+
+impl RequestTarget {
+    // Parse an origin-form ("/path?query"), absolute-form
+    // ("http://host/path?query") or asterisk-form ("*") target. Errors mean
+    // the client sent something malformed and should get a 400.
+    pub fn parse(raw: &str) -> Result<RequestTarget, String> {
+        // Asterisk-form, only used by "OPTIONS * HTTP/1.1"
+        if raw == "*" {
+            return Ok(RequestTarget {
+                raw: raw.to_string(),
+                path: raw.to_string(),
+                query: None,
+                query_params: Vec::new(),
+            });
+        }
+
+        // Clients shouldn't send fragments, but drop one if they do
+        let target = match raw.split_once('#') {
+            Some((before, _)) => before,
+            None => raw,
+        };
+
+        let (raw_path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+
+        // Absolute-form: strip the scheme and authority, keep the path
+        let raw_path = match raw_path.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") => {
+                match rest.find('/') {
+                    Some(slash) => &rest[slash..],
+                    None => "/",
+                }
+            }
+            _ => raw_path,
+        };
+
+        if !raw_path.starts_with('/') {
+            return Err(format!("request target must start with '/': {:?}", target));
+        }
+
+        // Decode each segment on its own so an encoded '/' can't create new segments
+        let mut segments = Vec::new();
+        for segment in raw_path[1..].split('/') {
+            let decoded = percent_decode(segment, false)?;
+            if decoded.contains(['/', '\0']) {
+                return Err(format!("invalid character in path segment {:?}", segment));
+            }
+            segments.push(decoded);
+        }
+        let path = format!("/{}", segments.join("/"));
+
+        let query_params = match query {
+            Some(query) => parse_query(query)?,
+            None => Vec::new(),
+        };
+
+        Ok(RequestTarget {
+            raw: raw.to_string(),
+            path,
+            query: query.map(str::to_string),
+            query_params,
+        })
+    }
+
+    // First value of a query parameter, e.g. "lang" in "?lang=en"
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query_params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Percent-encode a decoded path for use in a URL, leaving '/' and the
+// characters that never need escaping untouched
+pub fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// Split "a=1&b=two+words" into decoded (name, value) pairs
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, String> {
+    let mut params = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params.push((percent_decode(name, true)?, percent_decode(value, true)?));
+    }
+    Ok(params)
+}
+
+// Decode %XX escapes, and '+' as space when decoding form-style query strings.
+// Rejects truncated or non-hex escapes and results that aren't UTF-8.
+fn percent_decode(input: &str, plus_as_space: bool) -> Result<String, String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("malformed percent-encoding in {:?}", input))?;
+                decoded.push(hex);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| format!("percent-encoding in {:?} is not valid UTF-8", input))
+}
+
+impl std::fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_form_with_query() {
+        let target = RequestTarget::parse("/docs/my%20page.html?lang=en&q=a+b%21#top").unwrap();
+        assert_eq!(target.path, "/docs/my page.html");
+        assert_eq!(target.query.as_deref(), Some("lang=en&q=a+b%21"));
+        assert_eq!(target.query_param("lang"), Some("en"));
+        assert_eq!(target.query_param("q"), Some("a b!"));
+        assert_eq!(target.to_string(), "/docs/my%20page.html?lang=en&q=a+b%21#top");
+    }
+
+    #[test]
+    fn absolute_and_asterisk_forms() {
+        assert_eq!(RequestTarget::parse("http://example.com/a/b?x").unwrap().path, "/a/b");
+        assert_eq!(RequestTarget::parse("HTTPS://example.com").unwrap().path, "/");
+        assert_eq!(RequestTarget::parse("*").unwrap().path, "*");
+        assert!(RequestTarget::parse("ftp://example.com/a").is_err());
+        assert!(RequestTarget::parse("relative").is_err());
+    }
+
+    #[test]
+    fn plus_is_only_a_space_in_the_query() {
+        let target = RequestTarget::parse("/a+b?c=d+e").unwrap();
+        assert_eq!(target.path, "/a+b");
+        assert_eq!(target.query_param("c"), Some("d e"));
+    }
+
+    #[test]
+    fn decodes_utf8() {
+        assert_eq!(RequestTarget::parse("/caf%C3%A9").unwrap().path, "/café");
+        assert!(RequestTarget::parse("/%FF").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_escapes() {
+        for raw in ["/%", "/%4", "/%zz", "/%+1", "/?a=%g0"] {
+            assert!(RequestTarget::parse(raw).is_err(), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn rejects_encoded_slash_and_nul() {
+        assert!(RequestTarget::parse("/a%2Fb").is_err());
+        assert!(RequestTarget::parse("/a%2fb").is_err());
+        assert!(RequestTarget::parse("/a%00b").is_err());
+    }
+
+    #[test]
+    fn dot_segments_are_left_for_the_sandbox() {
+        assert_eq!(RequestTarget::parse("/a/%2E%2E/b").unwrap().path, "/a/../b");
+    }
+
+    #[test]
+    fn encode_path_round_trips() {
+        let path = "/dir/my file#1?.html";
+        let encoded = percent_encode_path(path);
+        assert_eq!(encoded, "/dir/my%20file%231%3F.html");
+        assert_eq!(RequestTarget::parse(&encoded).unwrap().path, path);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+
+// Response payload, either in memory or streamed from a file while writing
+#[derive(Debug, Clone)]
+pub enum Body {
+    Bytes(Vec<u8>),
+    // `length` bytes of `file` starting at `offset`, copied in chunks so
+    // large files never sit in memory. On Linux std::io::copy hands this to
+    // sendfile/splice when writing straight to a socket.
+    File {
+        file: Arc<File>,
+        offset: u64,
+        length: u64,
+    },
+    // Several bodies sent back to back, used for multipart/byteranges
+    Multi(Vec<Body>),
+}
+
+impl Body {
+    pub fn empty() -> Body {
+        Body::Bytes(Vec::new())
+    }
+
+    pub fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File { length, .. } => *length,
+            Body::Multi(parts) => parts.iter().map(Body::len).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Collect the whole body in memory, e.g. to compress it
+    pub fn read_all(&self) -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::with_capacity(self.len() as usize);
+        self.write_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Body::Bytes(bytes) => writer.write_all(bytes),
+            Body::File { file, offset, length } => {
+                let mut file: &File = file;
+                file.seek(SeekFrom::Start(*offset))?;
+                let copied = io::copy(&mut file.take(*length), writer)?;
+                if copied < *length {
+                    // The file shrank after Content-Length was sent
+                    return Err(io::Error::new(ErrorKind::UnexpectedEof, "file truncated while sending"));
+                }
+                Ok(())
+            }
+            Body::Multi(parts) => parts.iter().try_for_each(|part| part.write_to(writer)),
+        }
+    }
+}
+
+// An HTTP response ready to be serialized onto a stream
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Body::empty(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.set_header(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> Response {
+        self.body = Body::Bytes(body);
+        self
+    }
+
+    // Stream `length` bytes of `file` from `offset` as the body
+    pub fn with_file_body(mut self, file: Arc<File>, offset: u64, length: u64) -> Response {
+        self.body = Body::File { file, offset, length };
+        self
+    }
+
+    // Replace any existing header with the same name, or append a new one
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        match self
+            .headers
+            .iter_mut()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        {
+            Some(header) => header.1 = value.to_string(),
+            None => self.headers.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Status line and headers, ending with the blank line. Content-Length is
+    // filled in from the body unless a header already sets it or the status
+    // never carries a body.
+    pub fn serialize_head(&self) -> String {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if self.header("Content-Length").is_none() && !matches!(self.status, 204 | 304) {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        head
+    }
+
+    // Write the head followed by the body bytes
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.serialize_head().as_bytes())?;
+        self.body.write_to(writer)?;
+        writer.flush()
+    }
+}
+
+// Standard reason phrase for the status codes this server sends
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
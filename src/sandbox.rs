@@ -0,0 +1,146 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Component, Path, PathBuf},
+};
+
+// How symlinks inside the document root are treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    // Refuse any path that passes through a symlink
+    Never,
+    // Follow symlinks as long as the target stays inside the root
+    WithinRoot,
+    // Follow symlinks wherever they point
+    Always,
+}
+
+impl SymlinkPolicy {
+    pub fn parse(value: &str) -> Option<SymlinkPolicy> {
+        match value.to_lowercase().replace('_', "-").as_str() {
+            "never" => Some(SymlinkPolicy::Never),
+            "within-root" => Some(SymlinkPolicy::WithinRoot),
+            "always" => Some(SymlinkPolicy::Always),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SandboxError {
+    // The path escapes the root or breaks the symlink policy (403)
+    Forbidden(String),
+    // Nothing exists at the resolved path (404)
+    NotFound,
+    // The filesystem refused to resolve the path (500)
+    Io(std::io::Error),
+}
+
+// Map a decoded URL path onto a file under `root`. The path is normalized
+// segment by segment, then canonicalized and checked again so symlinks can't
+// be used to step outside the root.
+pub fn resolve_path(root: &Path, url_path: &str, policy: SymlinkPolicy) -> Result<PathBuf, SandboxError> {
+    // The loader canonicalizes pages_dir, but a Config built by hand may hold
+    // a relative root or one with ".." in it
+    let root = &fs::canonicalize(root).map_err(SandboxError::Io)?;
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in url_path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(SandboxError::Forbidden(format!("path climbs above the root: {}", url_path)));
+                }
+            }
+            _ => {
+                // A backslash or drive prefix would be a separator on Windows
+                if segment.contains('\\') || Path::new(segment).components().any(|c| !matches!(c, Component::Normal(_))) {
+                    return Err(SandboxError::Forbidden(format!("invalid path segment: {:?}", segment)));
+                }
+                segments.push(segment);
+            }
+        }
+    }
+
+    let mut full_path = root.to_path_buf();
+    for segment in &segments {
+        full_path.push(segment);
+
+        if policy == SymlinkPolicy::Never {
+            match fs::symlink_metadata(&full_path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(SandboxError::Forbidden(format!("symlink not allowed: {:?}", full_path)));
+                }
+                Ok(_) => {}
+                Err(e) if is_missing(&e) => return Err(SandboxError::NotFound),
+                Err(e) => return Err(SandboxError::Io(e)),
+            }
+        }
+    }
+
+    let canonical = match fs::canonicalize(&full_path) {
+        Ok(canonical) => canonical,
+        Err(e) if is_missing(&e) => return Err(SandboxError::NotFound),
+        Err(e) => return Err(SandboxError::Io(e)),
+    };
+
+    if policy != SymlinkPolicy::Always && !canonical.starts_with(root) {
+        return Err(SandboxError::Forbidden(format!("{:?} resolves outside the root", full_path)));
+    }
+
+    Ok(canonical)
+}
+
+// Nothing at that path, including "/file.html/x" where a file is used as a directory
+fn is_missing(error: &io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("simple_http_sandbox_{}_{}", name, std::process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("index.html"), "hi").unwrap();
+        fs::canonicalize(root).unwrap()
+    }
+
+    #[test]
+    fn resolves_files_inside_the_root() {
+        let root = temp_root("inside");
+        let resolved = resolve_path(&root, "/sub/../index.html", SymlinkPolicy::WithinRoot).unwrap();
+        assert_eq!(resolved, root.join("index.html"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_climbing_above_the_root() {
+        let root = temp_root("climb");
+        let result = resolve_path(&root, "/../etc/passwd", SymlinkPolicy::WithinRoot);
+        assert!(matches!(result, Err(SandboxError::Forbidden(_))));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn relative_and_unnormalized_roots() {
+        // Tests run from the package directory
+        let expected = fs::canonicalize("src/lib.rs").unwrap();
+        assert_eq!(resolve_path(Path::new("src"), "/lib.rs", SymlinkPolicy::WithinRoot).unwrap(), expected);
+        assert_eq!(resolve_path(Path::new("src/../src"), "/lib.rs", SymlinkPolicy::Never).unwrap(), expected);
+        let result = resolve_path(Path::new("src"), "/../Cargo.toml", SymlinkPolicy::WithinRoot);
+        assert!(matches!(result, Err(SandboxError::Forbidden(_))));
+    }
+
+    #[test]
+    fn file_used_as_directory_is_not_found() {
+        let root = temp_root("notdir");
+        for policy in [SymlinkPolicy::Never, SymlinkPolicy::WithinRoot] {
+            let result = resolve_path(&root, "/index.html/x", policy);
+            assert!(matches!(result, Err(SandboxError::NotFound)), "{:?}", policy);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,1027 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use crate::{
+    access_log::{AccessEntry, AccessLog},
+    compression::{self, Encoding},
+    config::{Config, ListenConfig, RESTART_SETTINGS},
+    debug, error, info,
+    listing::{self, SortKey},
+    log,
+    mime,
+    range::{self, ByteRange, RangeError},
+    reload,
+    request::{self, Request},
+    request_target::percent_encode_path,
+    response::{reason_phrase, Body, Response},
+    sandbox::{self, SandboxError, SymlinkPolicy},
+    shutdown,
+    sniff,
+    thread_pool::ThreadPool,
+    trace,
+    validators::Validators,
+    warn,
+};
+#[cfg(feature = "tls")]
+use crate::tls;
+#[cfg(unix)]
+use crate::unix_socket;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+// How connections accepted on a listener are handled
+#[derive(Clone)]
+pub enum Transport {
+    Plain,
+    #[cfg(feature = "tls")]
+    Tls(Arc<rustls::ServerConfig>),
+    // Answer every request with a redirect to the same URL over HTTPS on this port
+    RedirectToHttps(u16),
+}
+
+impl Transport {
+    // URL scheme clients use to reach this listener
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Transport::Plain | Transport::RedirectToHttps(_) => "http",
+            #[cfg(feature = "tls")]
+            Transport::Tls(_) => "https",
+        }
+    }
+}
+
+// A bound listening socket
+pub enum Socket {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl From<TcpListener> for Socket {
+    fn from(listener: TcpListener) -> Socket {
+        Socket::Tcp(listener)
+    }
+}
+
+// A bound socket, what to speak on it and an optional document root
+// replacing the configured pages_dir
+pub struct Listener {
+    pub listener: Socket,
+    pub transport: Transport,
+    pub pages_dir: Option<PathBuf>,
+}
+
+// Accept connections on every listener until a shutdown is requested, handing
+// each one to a shared pool of workers. Then lets in-flight connections finish
+// within the drain timeout, returning false if some had to be abandoned.
+// Reload requests re-read the configuration with Config::load.
+pub fn serve(listeners: Vec<Listener>, config: Config, access_log: AccessLog) -> bool {
+    // Hand connections to a pool of workers so one slow client can't block the rest
+    let pool = ThreadPool::new(config.workers, config.queue_capacity);
+    let roots = listeners.iter().map(|listener| listener.pages_dir.clone()).collect();
+    let shared = Shared::new(roots, Arc::new(config), Arc::new(access_log));
+
+    // Where the waker connects to unblock each accept, and Unix socket files
+    // to remove on the way out
+    let mut wake_targets = Vec::new();
+
+    thread::scope(|scope| {
+        for (index, Listener { listener, transport, .. }) in listeners.into_iter().enumerate() {
+            let (pool, shared) = (&pool, &shared);
+            match listener {
+                Socket::Tcp(listener) => {
+                    wake_targets.extend(listener.local_addr().ok().map(WakeTarget::Tcp));
+                    scope.spawn(move || accept_loop(listener.incoming(), transport, index, pool, shared, dispatch));
+                }
+                #[cfg(unix)]
+                Socket::Unix(listener) => {
+                    let path = listener.local_addr().ok().and_then(|addr| addr.as_pathname().map(PathBuf::from));
+                    wake_targets.extend(path.map(WakeTarget::Unix));
+                    scope.spawn(move || {
+                        accept_loop(listener.incoming(), transport, index, pool, shared, |stream, _, config, access_log| {
+                            unix_socket::handle_connection(stream, config, access_log)
+                        })
+                    });
+                }
+            }
+        }
+
+        // Watch for reloads until shutdown. Accept blocks, so then each
+        // listener is woken with a throwaway connection of our own.
+        let (shared, wake_targets) = (&shared, &wake_targets);
+        scope.spawn(move || {
+            let mut watcher = reload::Watcher::new(&shared.current().0);
+            while !shutdown::requested() {
+                thread::sleep(SHUTDOWN_POLL_INTERVAL);
+                if reload::take_requested() || watcher.changed() {
+                    shared.reload();
+                    watcher = reload::Watcher::new(&shared.current().0);
+                }
+            }
+            shutdown::wake_idle_connections();
+            for target in wake_targets {
+                target.wake();
+            }
+        });
+    });
+
+    #[cfg(unix)]
+    for target in &wake_targets {
+        if let WakeTarget::Unix(path) = target {
+            if let Err(e) = fs::remove_file(path) {
+                warn!("Failed to remove socket {:?}: {}", path, e);
+            }
+        }
+    }
+
+    let (config, access_log) = shared.current();
+    info!(
+        "Shutting down after {}, waiting up to {}s for open connections",
+        shutdown::reason(),
+        config.drain_timeout.as_secs()
+    );
+    let abandoned = pool.shutdown(config.drain_timeout);
+    access_log.flush();
+    if abandoned > 0 {
+        warn!("Drain timeout reached, closing {} connection(s) still in progress", abandoned);
+        return false;
+    }
+    info!("All connections finished");
+    true
+}
+
+// Settings used for new connections, replaced as a whole on reload, and the
+// access log, which is reopened in place instead
+struct Shared {
+    // Document root overrides, by listener index
+    roots: Vec<Option<PathBuf>>,
+    state: RwLock<SharedState>,
+    access_log: Arc<AccessLog>,
+}
+
+struct SharedState {
+    config: Arc<Config>,
+    // The config with each listener's document root applied, by listener index
+    listener_configs: Vec<Arc<Config>>,
+}
+
+impl Shared {
+    fn new(roots: Vec<Option<PathBuf>>, config: Arc<Config>, access_log: Arc<AccessLog>) -> Shared {
+        let state = Shared::build_state(&roots, config);
+        Shared {
+            roots,
+            state: RwLock::new(state),
+            access_log,
+        }
+    }
+
+    fn build_state(roots: &[Option<PathBuf>], config: Arc<Config>) -> SharedState {
+        let listener_configs = roots
+            .iter()
+            .map(|root| match root {
+                Some(pages_dir) => Arc::new(Config {
+                    pages_dir: pages_dir.clone(),
+                    ..Config::clone(&config)
+                }),
+                None => Arc::clone(&config),
+            })
+            .collect();
+        SharedState {
+            config,
+            listener_configs,
+        }
+    }
+
+    fn current(&self) -> (Arc<Config>, Arc<AccessLog>) {
+        let state = self.state.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        (Arc::clone(&state.config), Arc::clone(&self.access_log))
+    }
+
+    fn for_listener(&self, index: usize) -> (Arc<Config>, Arc<AccessLog>) {
+        let state = self.state.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        (Arc::clone(&state.listener_configs[index]), Arc::clone(&self.access_log))
+    }
+
+    // Re-read the configuration and swap it in, keeping the old one if it's invalid.
+    // The access log is always reopened, in place, so a rotated file gets replaced.
+    fn reload(&self) {
+        let (old, _) = self.current();
+        let mut config = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Reload failed, keeping the current configuration: {}", e);
+                return;
+            }
+        };
+        let access_log = match config.open_access_log() {
+            Ok(access_log) => access_log,
+            Err(e) => {
+                error!("Reload failed, keeping the current configuration: {}", e);
+                return;
+            }
+        };
+
+        let changes = config.changes_from(&old);
+        for (name, before, after) in &changes {
+            if RESTART_SETTINGS.contains(name) {
+                warn!("{} changed from {} to {}, restart to apply", name, before, after);
+            } else {
+                info!("{} changed from {} to {}", name, before, after);
+            }
+        }
+        config.keep_restart_settings(&old);
+
+        log::configure(config.log_filter.clone(), config.log_timestamps);
+        self.access_log.replace(access_log);
+        let state = Shared::build_state(&self.roots, Arc::new(config));
+        *self.state.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = state;
+        if changes.is_empty() {
+            info!("Configuration reloaded, nothing changed");
+        } else {
+            info!("Configuration reloaded, {} setting(s) changed", changes.len());
+        }
+    }
+}
+
+// Hand every accepted connection to the pool until a shutdown is requested
+fn accept_loop<S, I>(
+    incoming: I,
+    transport: Transport,
+    index: usize,
+    pool: &ThreadPool,
+    shared: &Shared,
+    handle: fn(S, &Transport, &Config, &AccessLog),
+) where
+    S: Send + 'static,
+    I: Iterator<Item = io::Result<S>>,
+{
+    for stream in incoming {
+        if shutdown::requested() {
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                // Each connection keeps the settings it was accepted under
+                let (config, access_log) = shared.for_listener(index);
+                let transport = transport.clone();
+                pool.execute(move || handle(stream, &transport, &config, &access_log));
+            }
+            Err(e) => {
+                warn!("Connection failed: {}", e);
+            }
+        }
+    }
+}
+
+enum WakeTarget {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl WakeTarget {
+    fn wake(&self) {
+        match self {
+            WakeTarget::Tcp(address) => {
+                let _ = TcpStream::connect(wake_address(*address));
+            }
+            #[cfg(unix)]
+            WakeTarget::Unix(path) => {
+                let _ = UnixStream::connect(path);
+            }
+        }
+    }
+}
+
+// How often the waker checks for shutdown and reload requests
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Address to connect to for reaching a listener, which for 0.0.0.0 or [::] is loopback
+fn wake_address(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), address.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), address.port()),
+        _ => address,
+    }
+}
+
+// What to listen on: each configured address with its transport, plus the
+// HTTP redirect address if set. TLS certificates are loaded here.
+pub fn plan_listeners(config: &Config) -> Result<Vec<(ListenConfig, Transport)>, String> {
+    let mut plan = Vec::new();
+    let mut https_port = None;
+    for listen in config.listeners() {
+        let transport = if listen.tls {
+            https_port = https_port.or_else(|| port_of(&listen.address));
+            tls_transport(config, &listen)?
+        } else {
+            Transport::Plain
+        };
+        plan.push((listen, transport));
+    }
+
+    // Plain HTTP requests on the redirect address are sent to the first HTTPS port
+    if let Some(address) = &config.redirect_http {
+        let redirect = ListenConfig {
+            address: address.clone(),
+            pages_dir: None,
+            tls: false,
+            tls_cert: None,
+            tls_key: None,
+            mode: None,
+        };
+        plan.push((redirect, Transport::RedirectToHttps(https_port.unwrap_or(443))));
+    }
+    Ok(plan)
+}
+
+#[cfg(feature = "tls")]
+fn tls_transport(config: &Config, listen: &ListenConfig) -> Result<Transport, String> {
+    let cert_and_key = match (&listen.tls_cert, &listen.tls_key, &config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key), _, _) | (None, None, Some(cert), Some(key)) => Some((cert.as_path(), key.as_path())),
+        _ => None,
+    };
+    tls::server_config(cert_and_key, &config.tls_sni).map(Transport::Tls)
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_transport(_config: &Config, _listen: &ListenConfig) -> Result<Transport, String> {
+    Err("TLS settings need a build with `--features tls`".to_string())
+}
+
+fn port_of(address: &str) -> Option<u16> {
+    address.to_socket_addrs().ok()?.next().map(|addr| addr.port())
+}
+
+fn dispatch(stream: TcpStream, transport: &Transport, config: &Config, access_log: &AccessLog) {
+    match transport {
+        Transport::Plain => handle_connection(stream, config, access_log),
+        #[cfg(feature = "tls")]
+        Transport::Tls(tls_config) => tls::handle_connection(stream, Arc::clone(tls_config), config, access_log),
+        Transport::RedirectToHttps(https_port) => {
+            let mut stream = stream;
+            if let Some(client) = prepare_tcp_stream(&stream, config) {
+                let connection = shutdown::Connection::tcp(&stream).ok();
+                handle_stream(&mut stream, connection.as_ref(), client, config, access_log, |request| {
+                    redirect_to_https(request, *https_port, config)
+                });
+            }
+        }
+    }
+}
+
+// Process a plain HTTP connection, serving requests until the client or a limit closes it
+pub fn handle_connection(mut stream: TcpStream, config: &Config, access_log: &AccessLog) {
+    if let Some(client) = prepare_tcp_stream(&stream, config) {
+        let connection = shutdown::Connection::tcp(&stream).ok();
+        handle_stream(&mut stream, connection.as_ref(), client, config, access_log, |request| {
+            handle_request(request, config)
+        });
+    }
+}
+
+// Apply the keep-alive timeout and look up the client address, None when the
+// connection is unusable
+pub fn prepare_tcp_stream(stream: &TcpStream, config: &Config) -> Option<Option<IpAddr>> {
+    // Idle persistent connections are dropped once the read times out
+    if let Err(e) = stream.set_read_timeout(Some(config.keep_alive_timeout)) {
+        warn!("Failed to set read timeout: {}", e);
+        return None;
+    }
+    Some(stream.peer_addr().ok().map(|addr| addr.ip()))
+}
+
+// Serve requests from any byte stream, answering each with `respond`. When
+// `connection` is given, shutdown can interrupt the wait between requests.
+pub fn handle_stream<S, F>(
+    stream: &mut S,
+    connection: Option<&shutdown::Connection>,
+    client: Option<IpAddr>,
+    config: &Config,
+    access_log: &AccessLog,
+    respond: F,
+) where
+    S: Read + Write,
+    F: Fn(&Request) -> Response,
+{
+    // Writes go through the reader so no buffered request bytes are lost
+    let mut buf_reader = BufReader::new(stream);
+
+    for served in 1..=config.max_requests_per_connection {
+        // Wait for the first byte of the next request
+        if let Some(connection) = connection {
+            connection.set_idle(true);
+        }
+        let waiting = buf_reader.fill_buf().map(|buffered| buffered.is_empty());
+        if let Some(connection) = connection {
+            connection.set_idle(false);
+        }
+        // Closed, timed out or interrupted by shutdown
+        if !matches!(waiting, Ok(false)) {
+            break;
+        }
+
+        let request = match request::read_request(&mut buf_reader, &config.limits) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(e) => {
+                info!("Rejected request: {}", e);
+                if let Some(status) = e.status() {
+                    let time = SystemTime::now();
+                    let started = Instant::now();
+                    let bytes = send_error_response(buf_reader.get_mut(), status, reason_phrase(status), config, false);
+                    access_log.log(&AccessEntry {
+                        client,
+                        time,
+                        request: None,
+                        status,
+                        bytes,
+                        latency: started.elapsed(),
+                    });
+                }
+                break;
+            }
+        };
+        let time = SystemTime::now();
+        let started = Instant::now();
+
+        // Dump the request headers
+        debug!("{}", request_dump(&request));
+
+        let mut response = respond(&request);
+
+        // The request was read in full, so a 4xx like a 404 can keep the
+        // connection. Server errors close it, as does running out of the
+        // request allowance or the server shutting down.
+        let remaining = config.max_requests_per_connection - served;
+        let keep_alive =
+            response.status < 500 && remaining > 0 && request.wants_keep_alive() && !shutdown::requested();
+        if keep_alive {
+            response.set_header("Connection", "keep-alive");
+            response.set_header(
+                "Keep-Alive",
+                &format!("timeout={}, max={}", config.keep_alive_timeout.as_secs(), remaining),
+            );
+        } else {
+            response.set_header("Connection", "close");
+        }
+
+        let written = write_response(buf_reader.get_mut(), &response);
+        access_log.log(&AccessEntry {
+            client,
+            time,
+            request: Some(&request),
+            status: response.status,
+            bytes: response.body.len(),
+            latency: started.elapsed(),
+        });
+        if !written || !keep_alive {
+            break;
+        }
+    }
+}
+
+// Send a plain HTTP request to the same host and URL over HTTPS
+fn redirect_to_https(request: &Request, https_port: u16, config: &Config) -> Response {
+    let host = match request.header("Host") {
+        Some(host) => strip_port(host.trim()),
+        None => return error_response(400, "Bad Request", config, false),
+    };
+    let port = if https_port == 443 {
+        String::new()
+    } else {
+        format!(":{}", https_port)
+    };
+
+    let mut location = format!("https://{}{}{}", host, port, percent_encode_path(&request.target.path));
+    if let Some(query) = &request.target.query {
+        location.push('?');
+        location.push_str(query);
+    }
+    Response::new(301).with_header("Location", &location)
+}
+
+// "example.com:80" -> "example.com", "[::1]:80" -> "[::1]"
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    }
+}
+
+// Methods this server answers, sent in Allow headers
+pub const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+// Build the response for one request without touching the network
+pub fn handle_request(request: &Request, config: &Config) -> Response {
+    // "*" only makes sense for OPTIONS, asking about the server as a whole
+    if request.target.path == "*" && request.method != "OPTIONS" {
+        return error_response(400, "Bad Request", config, false);
+    }
+
+    let mut response = match request.method.as_str() {
+        "GET" => serve_file(request, config),
+        "HEAD" => {
+            // Same headers as GET, including Content-Length, but no body
+            let mut response = serve_file(request, config);
+            if response.status != 304 {
+                let length = response.body.len().to_string();
+                response.set_header("Content-Length", &length);
+            }
+            response.body = Body::empty();
+            response
+        }
+        "OPTIONS" => Response::new(204).with_header("Allow", ALLOWED_METHODS),
+        _ => error_response(405, "Method Not Allowed", config, false),
+    };
+
+    // Without our own sniffing, stop browsers from second-guessing Content-Type
+    if !config.content_sniffing {
+        response.set_header("X-Content-Type-Options", "nosniff");
+    }
+    response
+}
+
+// Look up the requested file under the pages directory and return it
+fn serve_file(request: &Request, config: &Config) -> Response {
+    if !request.target.query_params.is_empty() {
+        trace!("Query parameters: {:?}", request.target.query_params);
+    }
+
+    let mut path = request.target.path.clone();
+
+    // Security: Resolve the path inside the pages directory, 403 if it escapes
+    let mut full_path = match resolve_or_error(&path, config) {
+        Ok(full_path) => full_path,
+        Err(response) => return response,
+    };
+
+    let mut metadata = match fs::metadata(&full_path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            error!("Error reading file {:?}: {}", full_path, e);
+            return error_response(500, "Error reading file", config, false);
+        }
+    };
+
+    // Directories are served through their index file or a generated listing
+    if metadata.is_dir() {
+        // Relative links only work from "/docs/", so send "/docs" there first
+        if !path.ends_with('/') {
+            return redirect_response(&format!("{}/", path), request);
+        }
+
+        match find_index_file(&path, config) {
+            Some((index_path, index_full_path, index_metadata)) => {
+                path = index_path;
+                full_path = index_full_path;
+                metadata = index_metadata;
+            }
+            None if config.directory_listing => return directory_listing(request, &path, &full_path, config),
+            None => return error_response(403, "Directory listing not allowed", config, true),
+        }
+    }
+
+    // Remove leading slash for logging and content type lookup
+    let filename = &path[1..];
+
+    // Determine content type based on file extension
+    let content_type = match config.mime_types.lookup(filename) {
+        Some(content_type) => content_type,
+        None if config.content_sniffing => sniff_content_type(&full_path),
+        None => mime::DEFAULT_TYPE.to_string(),
+    };
+
+    // Prefer a precompressed sibling (style.css.br, style.css.gz) the client
+    // accepts, then compress text-like files on the fly. Range requests are
+    // always answered from the original file so offsets stay meaningful.
+    let ranged = request.header("Range").is_some();
+    let accept_encoding = request.header("Accept-Encoding");
+    let compressible = config.compression && compression::is_compressible(&content_type);
+    let siblings = if config.precompressed && !ranged {
+        find_precompressed(&path, &metadata, config)
+    } else {
+        Vec::new()
+    };
+    let available: Vec<Encoding> = siblings.iter().map(|(encoding, _)| *encoding).collect();
+    let vary = compressible || !available.is_empty();
+
+    let mut encoding = Encoding::Identity;
+    let mut body_path = full_path;
+    let mut precompressed = false;
+    let sibling_encoding = compression::negotiate(accept_encoding, &available);
+    if let Some((sibling_encoding, sibling_path)) =
+        siblings.into_iter().find(|(encoding, _)| *encoding == sibling_encoding)
+    {
+        encoding = sibling_encoding;
+        body_path = sibling_path;
+        precompressed = true;
+    } else if compressible
+        && !ranged
+        && metadata.len() >= config.compression_min_size
+        && metadata.len() <= config.compression_max_size
+    {
+        encoding = compression::negotiate(accept_encoding, compression::RUNTIME_ENCODINGS);
+    }
+
+    // Keep the file open from here on so the validators and the body agree
+    let file = match File::open(&body_path).and_then(|file| Ok((file.metadata()?, file))) {
+        Ok((body_metadata, file)) => {
+            metadata = body_metadata;
+            Arc::new(file)
+        }
+        Err(e) => {
+            error!("Error reading file {:?}: {}", body_path, e);
+            return error_response(500, "Error reading file", config, false);
+        }
+    };
+    let total = metadata.len();
+
+    // Each coding is a different representation, so it gets its own tag
+    let mut validators = Validators::from_metadata(&metadata);
+    if encoding != Encoding::Identity {
+        validators.etag = format!("{}-{}\"", validators.etag.trim_end_matches('"'), encoding.name());
+    }
+
+    // Caches must keep one copy per Accept-Encoding for anything we might compress
+    let finish = |response: Response| {
+        let mut response = with_validators(response, &validators);
+        if vary {
+            response.set_header("Vary", "Accept-Encoding");
+        }
+        response
+    };
+
+    // Let clients with an up-to-date copy skip the download
+    if validators.is_not_modified(request) {
+        return finish(Response::new(304));
+    }
+
+    // Serve only the requested byte ranges when the client asks for them
+    if let Some(range_header) = request.header("Range") {
+        if validators.if_range_matches(request) {
+            match range::parse_range_header(range_header, total) {
+                Ok(ranges) => return finish(partial_response(file, total, &ranges, &content_type)),
+                Err(RangeError::Unsatisfiable) => {
+                    let mut response = error_response(416, "Range Not Satisfiable", config, false);
+                    response.set_header("Content-Range", &format!("bytes */{}", total));
+                    return response;
+                }
+                Err(RangeError::Ignored) => {}
+            }
+        }
+    }
+
+    // Files are streamed straight from disk, only on-the-fly compression
+    // needs the whole file in memory
+    let mut response = Response::new(200)
+        .with_header("Content-Type", &content_type)
+        .with_header("Accept-Ranges", "bytes")
+        .with_file_body(file, 0, total);
+    if precompressed {
+        response.set_header("Content-Encoding", encoding.name());
+    } else if encoding != Encoding::Identity {
+        let contents = match response.body.read_all() {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("Error reading file {:?}: {}", body_path, e);
+                return error_response(500, "Error reading file", config, false);
+            }
+        };
+        if let Some(compressed) = compression::compress(&contents, encoding) {
+            response.set_header("Content-Encoding", encoding.name());
+            response.body = Body::Bytes(compressed);
+        }
+    }
+    finish(response)
+}
+
+// Content type from the first bytes of a file with no known extension
+fn sniff_content_type(path: &Path) -> String {
+    let mut sample = Vec::with_capacity(sniff::SNIFF_LENGTH);
+    let sniffed = File::open(path)
+        .and_then(|file| file.take(sniff::SNIFF_LENGTH as u64).read_to_end(&mut sample))
+        .ok()
+        .and_then(|_| sniff::sniff(&sample));
+    match sniffed {
+        Some(mime_type) => mime::with_charset(mime_type),
+        None => mime::DEFAULT_TYPE.to_string(),
+    }
+}
+
+// Precompressed siblings of `path` that exist and are at least as new as the original
+fn find_precompressed(path: &str, original: &Metadata, config: &Config) -> Vec<(Encoding, PathBuf)> {
+    compression::PRECOMPRESSED_SUFFIXES
+        .iter()
+        .filter_map(|(encoding, suffix)| {
+            let sibling_path = format!("{}.{}", path, suffix);
+            let sibling = sandbox::resolve_path(&config.pages_dir, &sibling_path, config.symlinks).ok()?;
+            let metadata = fs::metadata(&sibling).ok()?;
+            let fresh = match (metadata.modified(), original.modified()) {
+                (Ok(sibling_time), Ok(original_time)) => sibling_time >= original_time,
+                _ => true,
+            };
+            (metadata.is_file() && fresh).then_some((*encoding, sibling))
+        })
+        .collect()
+}
+
+// Map a URL path to a file under the pages directory, or the error response to send
+fn resolve_or_error(path: &str, config: &Config) -> Result<PathBuf, Response> {
+    match sandbox::resolve_path(&config.pages_dir, path, config.symlinks) {
+        Ok(full_path) => Ok(full_path),
+        Err(SandboxError::Forbidden(reason)) => {
+            warn!("Blocked directory traversal attempt: {}", reason);
+            Err(error_response(403, "Directory traversal not allowed", config, true))
+        }
+        Err(SandboxError::NotFound) => {
+            debug!("File not found: {}", &path[1..]);
+            Err(error_response(404, "File Not Found", config, true))
+        }
+        Err(SandboxError::Io(e)) => {
+            error!("Error resolving path {:?}: {}", path, e);
+            Err(error_response(500, "Error reading file", config, false))
+        }
+    }
+}
+
+// First configured index file that exists in the directory at `dir_path`,
+// as (URL path, resolved path, metadata)
+fn find_index_file(dir_path: &str, config: &Config) -> Option<(String, PathBuf, Metadata)> {
+    config.index_files.iter().find_map(|index_file| {
+        let index_path = format!("{}{}", dir_path, index_file);
+        let full_path = sandbox::resolve_path(&config.pages_dir, &index_path, config.symlinks).ok()?;
+        let metadata = fs::metadata(&full_path).ok()?;
+        metadata.is_file().then_some((index_path, full_path, metadata))
+    })
+}
+
+// 301 to `path`, keeping the query string
+fn redirect_response(path: &str, request: &Request) -> Response {
+    let mut location = percent_encode_path(path);
+    if let Some(query) = &request.target.query {
+        location.push('?');
+        location.push_str(query);
+    }
+
+    let body = format!("Moved to {}", location);
+    Response::new(301)
+        .with_header("Location", &location)
+        .with_header("Content-Type", "text/plain")
+        .with_body(body.into_bytes())
+}
+
+// Generated index of a directory, as HTML or as JSON when asked for with
+// ?format=json or an Accept header preferring application/json.
+// ?sort=name|size|modified and ?order=asc|desc control the ordering.
+fn directory_listing(request: &Request, path: &str, dir: &Path, config: &Config) -> Response {
+    let mut entries = match listing::read_entries(dir, config.symlinks != SymlinkPolicy::Never) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Error listing directory {:?}: {}", dir, e);
+            return error_response(500, "Error reading directory", config, false);
+        }
+    };
+
+    let key = request
+        .target
+        .query_param("sort")
+        .and_then(SortKey::parse)
+        .unwrap_or(SortKey::Name);
+    let descending = request.target.query_param("order") == Some("desc");
+    listing::sort_entries(&mut entries, key, descending);
+
+    let wants_json = match request.target.query_param("format") {
+        Some(format) => format == "json",
+        None => request
+            .header("Accept")
+            .is_some_and(|accept| accept.contains("application/json") && !accept.contains("text/html")),
+    };
+
+    let (body, content_type) = if wants_json {
+        (listing::render_json(path, &entries), "application/json")
+    } else {
+        (listing::render_html(path, &entries, key, descending), "text/html; charset=utf-8")
+    };
+
+    Response::new(200)
+        .with_header("Content-Type", content_type)
+        .with_header("Vary", "Accept")
+        .with_body(body.into_bytes())
+}
+
+// 206 response with one range inline or several as multipart/byteranges
+fn partial_response(file: Arc<File>, total: u64, ranges: &[ByteRange], content_type: &str) -> Response {
+    let response = Response::new(206).with_header("Accept-Ranges", "bytes");
+
+    if let [range] = ranges {
+        return response
+            .with_header("Content-Type", content_type)
+            .with_header("Content-Range", &range.content_range(total))
+            .with_file_body(file, range.start, range.end - range.start + 1);
+    }
+
+    let boundary = range::multipart_boundary();
+    let mut response = response.with_header("Content-Type", &format!("multipart/byteranges; boundary={}", boundary));
+    response.body = range::multipart_body(file, total, ranges, content_type, &boundary);
+    response
+}
+
+// Attach ETag and Last-Modified so clients can revalidate later
+fn with_validators(mut response: Response, validators: &Validators) -> Response {
+    response.set_header("ETag", &validators.etag);
+    if let Some(last_modified) = validators.last_modified_header() {
+        response.set_header("Last-Modified", &last_modified);
+    }
+    response
+}
+
+// Serialize a response onto the stream, returns false if the write failed
+fn write_response<W: Write>(stream: &mut W, response: &Response) -> bool {
+    // Dump the response headers (without body)
+    debug!("{}", response_dump(response));
+
+    if let Err(e) = response.write_to(stream) {
+        warn!("Failed to send response: {}", e);
+        return false;
+    }
+    true
+}
+
+// Request line and headers between banners, for debug logging
+fn request_dump(request: &Request) -> String {
+    let mut dump = String::from("=== HTTP Request Received ===\n");
+    dump.push_str(&format!("{} {} {}\n", request.method, request.target, request.version));
+    for (name, value) in &request.headers {
+        dump.push_str(&format!("{}: {}\n", name, value));
+    }
+    dump.push_str("=============================");
+    dump
+}
+
+// Status line and headers between banners, for debug logging
+fn response_dump(response: &Response) -> String {
+    let mut dump = if response.status >= 400 {
+        String::from("=== HTTP Error Response ===\n")
+    } else {
+        String::from("=== HTTP Response Sent ===\n")
+    };
+    for line in response.serialize_head().split("\r\n") {
+        if !line.is_empty() {
+            dump.push_str(line);
+            dump.push('\n');
+        }
+    }
+    dump.push_str("===========================");
+    dump
+}
+
+// Handle errors, returns the body size for the access log
+fn send_error_response<W: Write>(stream: &mut W, status: u16, message: &str, config: &Config, try_html: bool) -> u64 {
+    let response = error_response(status, message, config, try_html).with_header("Connection", "close");
+    write_response(stream, &response);
+    response.body.len()
+}
+
+// Build an error response, using pages/<status>.html when `try_html` is set and it exists
+pub fn error_response(status: u16, message: &str, config: &Config, try_html: bool) -> Response {
+    let (content, content_type) = if try_html {
+        // Check if there's a custom error page for this status code
+        let error_page_path = config.pages_dir.join(format!("{}.html", status));
+
+        if error_page_path.exists() {
+            // Serve the custom error page
+            match fs::read(&error_page_path) {
+                Ok(content) => (content, "text/html"),
+                Err(_) => (message.as_bytes().to_vec(), "text/plain"),
+            }
+        } else {
+            // Fall back to plain text message
+            (message.as_bytes().to_vec(), "text/plain")
+        }
+    } else {
+        // Use plain text for non-HTML errors
+        (message.as_bytes().to_vec(), "text/plain")
+    };
+
+    let mut response = Response::new(status)
+        .with_header("Content-Type", content_type)
+        .with_body(content);
+
+    // A 405 has to tell the client which methods would have worked
+    if status == 405 {
+        response.set_header("Allow", ALLOWED_METHODS);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads a canned request stream and collects everything written back
+    struct FakeStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn exchange(input: &str, respond: impl Fn(&Request) -> Response) -> String {
+        let mut stream = FakeStream {
+            input: io::Cursor::new(input.as_bytes().to_vec()),
+            output: Vec::new(),
+        };
+        handle_stream(&mut stream, None, None, &Config::default(), &AccessLog::disabled(), respond);
+        String::from_utf8_lossy(&stream.output).into_owned()
+    }
+
+    fn statuses(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter(|line| line.starts_with("HTTP/1.1 "))
+            .map(|line| &line[9..12])
+            .collect()
+    }
+
+    fn get(path: &str) -> Request {
+        Request::parse_head(&[format!("GET {} HTTP/1.1", path)]).unwrap()
+    }
+
+    #[test]
+    fn serves_from_a_relative_root() {
+        // Tests run from the package directory
+        let config = Config {
+            pages_dir: PathBuf::from("src/../src"),
+            ..Config::default()
+        };
+        assert_eq!(handle_request(&get("/lib.rs"), &config).status, 200);
+        assert_eq!(handle_request(&get("/../Cargo.toml"), &config).status, 403);
+    }
+
+    #[test]
+    fn nosniff_only_without_content_sniffing() {
+        let root = std::env::temp_dir().join(format!("simple_http_nosniff_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("README"), "plain words").unwrap();
+        let mut config = Config {
+            pages_dir: root.clone(),
+            ..Config::default()
+        };
+
+        config.content_sniffing = true;
+        let sniffed = handle_request(&get("/README"), &config);
+        config.content_sniffing = false;
+        let unsniffed = handle_request(&get("/README"), &config);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(sniffed.header("Content-Type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(sniffed.header("X-Content-Type-Options"), None);
+        assert_eq!(unsniffed.header("Content-Type"), Some(mime::DEFAULT_TYPE));
+        assert_eq!(unsniffed.header("X-Content-Type-Options"), Some("nosniff"));
+    }
+
+    #[test]
+    fn client_errors_keep_the_connection_open() {
+        let output = exchange("GET /missing HTTP/1.1\r\n\r\nGET /next HTTP/1.1\r\n\r\n", |request| {
+            let status = if request.target.path == "/missing" { 404 } else { 200 };
+            Response::new(status)
+        });
+        assert_eq!(statuses(&output), ["404", "200"]);
+        assert!(output.contains("Connection: keep-alive"));
+    }
+
+    #[test]
+    fn server_errors_close_the_connection() {
+        let output = exchange("GET / HTTP/1.1\r\n\r\nGET /next HTTP/1.1\r\n\r\n", |_| Response::new(500));
+        assert_eq!(statuses(&output), ["500"]);
+        assert!(output.contains("Connection: close"));
+    }
+
+    #[test]
+    fn parse_errors_close_the_connection() {
+        let output = exchange("BAD\r\n\r\nGET / HTTP/1.1\r\n\r\n", |_| Response::new(200));
+        assert_eq!(statuses(&output), ["400"]);
+        assert!(output.contains("Connection: close"));
+    }
+}
@@ -0,0 +1,139 @@
+// Shutdown requests from SIGINT/SIGTERM or from code embedding the server.
+// The signal handler only sets a flag, the accept loops in server::serve
+// notice it and stop taking new connections.
+use std::{
+    io,
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+// Signal that asked for the shutdown, 0 when it came from request()
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+// Keep-alive connections waiting for their next request
+static IDLE: Mutex<Vec<Arc<Stream>>> = Mutex::new(Vec::new());
+
+// Also used by reload for SIGHUP
+#[cfg(unix)]
+pub(crate) mod sys {
+    use std::os::raw::c_int;
+
+    pub const SIGHUP: c_int = 1;
+    pub const SIGINT: c_int = 2;
+    pub const SIGTERM: c_int = 15;
+
+    extern "C" {
+        // Returns the previous handler, which we don't need
+        pub fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signum: std::os::raw::c_int) {
+    // Only async-signal-safe work here: two atomic stores
+    SIGNAL.store(signum, Ordering::SeqCst);
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Route SIGINT and SIGTERM to a graceful shutdown. Does nothing off Unix,
+// where Ctrl-C still ends the process immediately.
+pub fn install_handlers() {
+    #[cfg(unix)]
+    unsafe {
+        sys::signal(sys::SIGINT, on_signal);
+        sys::signal(sys::SIGTERM, on_signal);
+    }
+}
+
+// Ask a running server to shut down, as if it had received SIGTERM
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+// Name of the signal that asked for the shutdown, for log messages
+pub fn reason() -> &'static str {
+    match SIGNAL.load(Ordering::SeqCst) {
+        2 => "SIGINT",
+        15 => "SIGTERM",
+        _ => "shutdown request",
+    }
+}
+
+// Interrupt every connection waiting for its next request, so draining
+// doesn't sit out their keep-alive timeouts. Called by server::serve once
+// shutdown starts.
+pub fn wake_idle_connections() {
+    for stream in lock_idle().iter() {
+        stream.close_read();
+    }
+}
+
+// A client connection that shutdown can interrupt while it is idle
+pub struct Connection {
+    stream: Arc<Stream>,
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    // The blocked read sees end of stream, writes still go through
+    fn close_read(&self) {
+        let _ = match self {
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Read),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Read),
+        };
+    }
+}
+
+impl Connection {
+    pub fn tcp(stream: &TcpStream) -> io::Result<Connection> {
+        Ok(Connection::new(Stream::Tcp(stream.try_clone()?)))
+    }
+
+    #[cfg(unix)]
+    pub fn unix(stream: &UnixStream) -> io::Result<Connection> {
+        Ok(Connection::new(Stream::Unix(stream.try_clone()?)))
+    }
+
+    fn new(stream: Stream) -> Connection {
+        Connection { stream: Arc::new(stream) }
+    }
+
+    // Mark the connection as waiting for a request or not. A wait that
+    // starts after shutdown began is interrupted right away.
+    pub fn set_idle(&self, idle: bool) {
+        let mut connections = lock_idle();
+        connections.retain(|stream| !Arc::ptr_eq(stream, &self.stream));
+        if idle {
+            connections.push(Arc::clone(&self.stream));
+            drop(connections);
+            if requested() {
+                self.stream.close_read();
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.set_idle(false);
+    }
+}
+
+fn lock_idle() -> std::sync::MutexGuard<'static, Vec<Arc<Stream>>> {
+    IDLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
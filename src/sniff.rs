@@ -0,0 +1,97 @@
+// Guess a content type from the first bytes of a file, for files whose
+// extension didn't tell us. Only a few unambiguous formats are recognized.
+
+// How much of the file the sniffer looks at
+pub const SNIFF_LENGTH: usize = 512;
+
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    let bytes = &bytes[..bytes.len().min(SNIFF_LENGTH)];
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if bytes.starts_with(b"\xff\xd8\xff") {
+        return Some("image/jpeg");
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if bytes.starts_with(b"%PDF-") {
+        return Some("application/pdf");
+    }
+
+    // Everything below is text, which has to be UTF-8 without control bytes
+    let text = utf8_prefix(bytes)?;
+    if text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b')) {
+        return None;
+    }
+
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+    let lower: String = trimmed.chars().take(16).collect::<String>().to_lowercase();
+    if ["<!doctype html", "<html", "<head", "<body", "<!--"]
+        .iter()
+        .any(|prefix| lower.starts_with(prefix))
+    {
+        return Some("text/html");
+    }
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        return Some("application/json");
+    }
+    Some("text/plain")
+}
+
+// The sample as text, tolerating a character cut off by the sample length
+fn utf8_prefix(bytes: &[u8]) -> Option<&str> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&bytes[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_formats() {
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", Some("image/png")),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", Some("image/jpeg")),
+            (b"GIF87a\x01\0\x01\0", Some("image/gif")),
+            (b"GIF89a\x01\0\x01\0", Some("image/gif")),
+            (b"%PDF-1.7\n%\xe2\xe3\xcf\xd3", Some("application/pdf")),
+            (b"<!DOCTYPE html>\n<title>x</title>", Some("text/html")),
+            (b"\xef\xbb\xbf  \n<!doctype HTML>", Some("text/html")),
+            (b"\n\t<html lang=en>", Some("text/html")),
+            (b"<!-- comment -->", Some("text/html")),
+            (b"{\"key\": [1, 2]}", Some("application/json")),
+            (b"  [1, 2, 3]", Some("application/json")),
+            ("plain text, caf\u{e9} \u{2713}\r\n".as_bytes(), Some("text/plain")),
+            (b"\x1b[1mbold\x1b[0m", Some("text/plain")),
+            (b"", Some("text/plain")),
+            (b"ELF\x02\x01\x01\0\0\0", None),
+            (b"text with a \x07 bell", None),
+            (b"\xff\xfe not utf-8", None),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(sniff(bytes), *expected, "{:?}", String::from_utf8_lossy(bytes));
+        }
+    }
+
+    #[test]
+    fn character_cut_at_the_sample_length_is_still_text() {
+        let mut bytes = vec![b'a'; SNIFF_LENGTH - 1];
+        bytes.extend_from_slice("\u{e9}".as_bytes());
+        assert_eq!(sniff(&bytes), Some("text/plain"));
+        bytes.truncate(SNIFF_LENGTH);
+        assert_eq!(sniff(&bytes), Some("text/plain"));
+    }
+
+    #[test]
+    fn only_the_sample_is_examined() {
+        let mut bytes = vec![b'a'; SNIFF_LENGTH];
+        bytes.push(0);
+        assert_eq!(sniff(&bytes), Some("text/plain"));
+    }
+}
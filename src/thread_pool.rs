@@ -0,0 +1,225 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use crate::{error, warn};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Fixed set of worker threads fed from a bounded job queue
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::SyncSender<Job>>,
+}
+
+impl ThreadPool {
+    // Create a pool with `size` workers and room for `queue_capacity` waiting jobs.
+    // Panics if `size` is zero.
+    pub fn new(size: usize, queue_capacity: usize) -> ThreadPool {
+        assert!(size > 0, "thread pool needs at least one worker");
+
+        // A sync channel blocks the sender once the queue is full, which gives
+        // backpressure on the accept loop instead of an unbounded backlog
+        let (sender, receiver) = mpsc::sync_channel(queue_capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|id| Worker::new(id, Arc::clone(&receiver)))
+            .collect();
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    // Queue a job, blocking while the queue is full
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(sender) = &self.sender {
+            if sender.send(Box::new(f)).is_err() {
+                error!("Thread pool has shut down, dropping job");
+            }
+        }
+    }
+
+    // Close the queue and wait up to `timeout` for the workers to finish the
+    // jobs already queued. Returns how many were still busy when time ran out;
+    // those threads are left running and die with the process.
+    pub fn shutdown(mut self, timeout: Duration) -> usize {
+        drop(self.sender.take());
+
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline && !self.workers.iter().all(Worker::is_finished) {
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        let mut busy = 0;
+        for worker in &mut self.workers {
+            if worker.is_finished() {
+                if let Some(thread) = worker.thread.take() {
+                    if thread.join().is_err() {
+                        error!("Worker {} panicked", worker.id);
+                    }
+                }
+            } else {
+                // Detach it so Drop doesn't wait
+                worker.thread = None;
+                busy += 1;
+            }
+        }
+        busy
+    }
+}
+
+impl Drop for ThreadPool {
+    // Close the queue and wait for every worker to finish its remaining jobs
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                if thread.join().is_err() {
+                    error!("Worker {} panicked", worker.id);
+                }
+            }
+        }
+    }
+}
+
+struct Worker {
+    id: usize,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|thread| thread.is_finished())
+    }
+
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+        let thread = thread::spawn(move || loop {
+            // Hold the lock only while waiting for the next job
+            let message = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => break,
+            };
+
+            match message {
+                // Keep the worker alive if a single connection handler panics
+                Ok(job) => {
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        warn!("Worker {} recovered from a panicking job", id);
+                    }
+                }
+                // The sender was dropped, so the pool is shutting down
+                Err(_) => break,
+            }
+        });
+
+        Worker {
+            id,
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    // A job that reports when it starts, then blocks until released
+    fn blocking_job(started: mpsc::Sender<()>, release: Arc<Mutex<mpsc::Receiver<()>>>) -> impl FnOnce() + Send {
+        move || {
+            started.send(()).unwrap();
+            let _ = release.lock().unwrap().recv();
+        }
+    }
+
+    #[test]
+    fn full_queue_blocks_execute() {
+        let pool = ThreadPool::new(1, 1);
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+
+        // One job running, one waiting in the queue
+        pool.execute(blocking_job(started.clone(), Arc::clone(&release_rx)));
+        started_rx.recv_timeout(WAIT).unwrap();
+        pool.execute(|| {});
+
+        let queued = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                pool.execute(|| {});
+                queued.store(true, Ordering::SeqCst);
+            });
+            thread::sleep(Duration::from_millis(200));
+            assert!(!queued.load(Ordering::SeqCst), "execute should wait for room in the queue");
+            release.send(()).unwrap();
+        });
+        assert!(queued.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn shutdown_reports_and_detaches_stuck_workers() {
+        let pool = ThreadPool::new(2, 4);
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        pool.execute(blocking_job(started, Arc::new(Mutex::new(release_rx))));
+        started_rx.recv_timeout(WAIT).unwrap();
+        pool.execute(|| thread::sleep(Duration::from_millis(10)));
+
+        let begun = Instant::now();
+        assert_eq!(pool.shutdown(Duration::from_millis(200)), 1);
+        assert!(begun.elapsed() < WAIT);
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn shutdown_waits_for_queued_jobs() {
+        let pool = ThreadPool::new(2, 8);
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(20));
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(pool.shutdown(WAIT), 0);
+        assert_eq!(done.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn worker_survives_a_panicking_job() {
+        let pool = ThreadPool::new(1, 2);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(|| panic!("job failed"));
+        pool.execute(move || sender.send(()).unwrap());
+        receiver.recv_timeout(WAIT).unwrap();
+    }
+
+    #[test]
+    fn drop_joins_workers() {
+        let done = Arc::new(AtomicUsize::new(0));
+        {
+            let pool = ThreadPool::new(3, 8);
+            for _ in 0..8 {
+                let done = Arc::clone(&done);
+                pool.execute(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        }
+        assert_eq!(done.load(Ordering::SeqCst), 8);
+    }
+}
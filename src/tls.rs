@@ -0,0 +1,111 @@
+// HTTPS listeners through rustls. The certificate is chosen per connection
+// from the SNI host name, falling back to the default tls_cert/tls_key pair.
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+};
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig, ServerConnection, StreamOwned,
+};
+use crate::{
+    access_log::AccessLog,
+    config::{Config, SniCertificate},
+    debug,
+    server::{self, prepare_tcp_stream},
+    shutdown,
+};
+
+// Build the rustls configuration for one listener: its default certificate
+// and key, plus the SNI certificates
+pub fn server_config(cert_and_key: Option<(&Path, &Path)>, sni: &[SniCertificate]) -> Result<Arc<ServerConfig>, String> {
+    let provider = Arc::new(ring::default_provider());
+
+    let default = match cert_and_key {
+        Some((cert, key)) => Some(load_certified_key(cert, key, &provider)?),
+        None => None,
+    };
+    let mut by_name = HashMap::new();
+    for certificate in sni {
+        by_name.insert(
+            certificate.hostname.clone(),
+            load_certified_key(&certificate.cert, &certificate.key, &provider)?,
+        );
+    }
+
+    let mut tls_config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("cannot set up TLS: {}", e))?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(CertResolver { default, by_name }));
+    tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(tls_config))
+}
+
+// Run the TLS handshake and serve HTTP over it, like server::handle_connection
+pub fn handle_connection(stream: TcpStream, tls_config: Arc<ServerConfig>, config: &Config, access_log: &AccessLog) {
+    let client = match prepare_tcp_stream(&stream, config) {
+        Some(client) => client,
+        None => return,
+    };
+    let connection = match ServerConnection::new(tls_config) {
+        Ok(connection) => connection,
+        Err(e) => {
+            debug!("Failed to start TLS session: {}", e);
+            return;
+        }
+    };
+
+    // Tracked through the TCP socket underneath, the handshake runs on the first read
+    let idle = shutdown::Connection::tcp(&stream).ok();
+    let mut stream = StreamOwned::new(connection, stream);
+    server::handle_stream(&mut stream, idle.as_ref(), client, config, access_log, |request| {
+        server::handle_request(request, config)
+    });
+
+    stream.conn.send_close_notify();
+    let _ = stream.flush();
+}
+
+fn load_certified_key(cert: &Path, key: &Path, provider: &CryptoProvider) -> Result<Arc<CertifiedKey>, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("cannot read certificate {:?}: {}", cert, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {:?}", cert));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("cannot read private key {:?}: {}", key, e))?;
+
+    CertifiedKey::from_der(certs, private_key, provider)
+        .map(Arc::new)
+        .map_err(|e| format!("certificate {:?} does not match key {:?}: {}", cert, key, e))
+}
+
+// Picks a certificate by the host name in the client hello
+#[derive(Debug)]
+struct CertResolver {
+    default: Option<Arc<CertifiedKey>>,
+    // Lowercase host names, possibly wildcards like *.example.com
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let name = client_hello.server_name().map(str::to_lowercase);
+        name.and_then(|name| {
+            self.by_name.get(&name).or_else(|| {
+                let (_, parent) = name.split_once('.')?;
+                self.by_name.get(&format!("*.{}", parent))
+            })
+        })
+        .or(self.default.as_ref())
+        .cloned()
+    }
+}
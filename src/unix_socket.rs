@@ -0,0 +1,57 @@
+// Listening on Unix domain sockets, for running behind a local reverse proxy.
+// Requests are served exactly as over TCP, only without a client address.
+use std::{
+    fs::{self, Permissions},
+    io::ErrorKind,
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+use crate::{
+    access_log::AccessLog,
+    config::Config,
+    info,
+    server::{self, handle_request},
+    shutdown,
+    warn,
+};
+
+// Bind a socket at `path`, replacing a stale socket file left behind by a
+// previous run, and apply `mode` to the new file
+pub fn bind(path: &Path, mode: Option<u32>) -> Result<UnixListener, String> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            // A socket nobody answers on is left over from a server that died
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!("{:?} is in use by another server", path));
+            }
+            info!("Removing stale socket {:?}", path);
+            fs::remove_file(path).map_err(|e| format!("cannot remove stale socket {:?}: {}", path, e))?;
+        }
+        Ok(_) => return Err(format!("{:?} exists and is not a socket", path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("cannot check {:?}: {}", path, e)),
+    }
+
+    let listener = UnixListener::bind(path).map_err(|e| e.to_string())?;
+    if let Some(mode) = mode {
+        fs::set_permissions(path, Permissions::from_mode(mode))
+            .map_err(|e| format!("cannot set permissions on {:?}: {}", path, e))?;
+    }
+    Ok(listener)
+}
+
+// Serve requests on an accepted Unix socket connection, like server::handle_connection
+pub fn handle_connection(mut stream: UnixStream, config: &Config, access_log: &AccessLog) {
+    // Idle persistent connections are dropped once the read times out
+    if let Err(e) = stream.set_read_timeout(Some(config.keep_alive_timeout)) {
+        warn!("Failed to set read timeout: {}", e);
+        return;
+    }
+    let connection = shutdown::Connection::unix(&stream).ok();
+    server::handle_stream(&mut stream, connection.as_ref(), None, config, access_log, |request| {
+        handle_request(request, config)
+    });
+}
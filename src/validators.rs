@@ -0,0 +1,163 @@
+use std::{
+    fs::Metadata,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use crate::{
+    httpdate::{format_http_date, parse_http_date},
+    request::Request,
+};
+
+// Cache validators for a file, derived from its size and modification time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+    // Quoted entity tag, e.g. "18c5f1a2b.3b9aca00-1f0"
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    pub fn from_metadata(metadata: &Metadata) -> Validators {
+        let last_modified = metadata.modified().ok();
+        let stamp = last_modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| format!("{:x}.{:x}", since.as_secs(), since.subsec_nanos()))
+            .unwrap_or_else(|| "0".to_string());
+
+        Validators {
+            etag: format!("\"{}-{:x}\"", stamp, metadata.len()),
+            last_modified,
+        }
+    }
+
+    pub fn last_modified_header(&self) -> Option<String> {
+        self.last_modified.map(format_http_date)
+    }
+
+    // True when a GET or HEAD can be answered with 304 Not Modified.
+    // If-None-Match takes precedence, If-Modified-Since is only checked without it.
+    pub fn is_not_modified(&self, request: &Request) -> bool {
+        if let Some(if_none_match) = request.header("If-None-Match") {
+            return etag_list_matches(if_none_match, &self.etag);
+        }
+
+        if let (Some(since), Some(modified)) = (request.header("If-Modified-Since"), self.last_modified) {
+            if let Some(since) = parse_http_date(since) {
+                // HTTP dates only have whole seconds
+                return whole_seconds(modified) <= whole_seconds(since);
+            }
+        }
+
+        false
+    }
+
+    // True when a Range request may be honored. Without If-Range the range
+    // always applies, otherwise the client's validator has to match exactly.
+    pub fn if_range_matches(&self, request: &Request) -> bool {
+        let if_range = match request.header("If-Range") {
+            Some(if_range) => if_range.trim(),
+            None => return true,
+        };
+
+        // Entity tags use the strong comparison, so weak tags never match
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            return if_range == self.etag && !self.etag.starts_with("W/");
+        }
+
+        match (parse_http_date(if_range), self.last_modified) {
+            (Some(date), Some(modified)) => whole_seconds(date) == whole_seconds(modified),
+            _ => false,
+        }
+    }
+}
+
+// Compare a header value like `"a", W/"b"` or `*` against our tag.
+// Uses the weak comparison, so a W/ prefix on either side is ignored.
+pub fn etag_list_matches(header: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+fn whole_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn validators() -> Validators {
+        Validators {
+            etag: "\"abc\"".to_string(),
+            // Sun, 06 Nov 1994 08:49:37 GMT, with a fraction the header can't carry
+            last_modified: Some(UNIX_EPOCH + Duration::from_secs(784111777) + Duration::from_millis(500)),
+        }
+    }
+
+    fn request(headers: &[&str]) -> Request {
+        let mut lines = vec!["GET / HTTP/1.1".to_string()];
+        lines.extend(headers.iter().map(|header| header.to_string()));
+        Request::parse_head(&lines).unwrap()
+    }
+
+    #[test]
+    fn if_none_match() {
+        let validators = validators();
+        assert!(validators.is_not_modified(&request(&["If-None-Match: \"abc\""])));
+        assert!(validators.is_not_modified(&request(&["If-None-Match: \"x\", W/\"abc\""])));
+        assert!(validators.is_not_modified(&request(&["If-None-Match: *"])));
+        assert!(!validators.is_not_modified(&request(&["If-None-Match: \"abcd\""])));
+        assert!(!validators.is_not_modified(&request(&[])));
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence() {
+        let request = request(&["If-None-Match: \"other\"", "If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT"]);
+        assert!(!validators().is_not_modified(&request));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let validators = validators();
+        let since = |date: &str| request(&[&format!("If-Modified-Since: {}", date)]);
+        assert!(validators.is_not_modified(&since("Sun, 06 Nov 1994 08:49:37 GMT")));
+        assert!(validators.is_not_modified(&since("Mon, 07 Nov 1994 00:00:00 GMT")));
+        assert!(!validators.is_not_modified(&since("Sun, 06 Nov 1994 08:49:36 GMT")));
+        assert!(!validators.is_not_modified(&since("not a date")));
+    }
+
+    #[test]
+    fn if_range() {
+        let validators = validators();
+        let if_range = |value: &str| validators.if_range_matches(&request(&[&format!("If-Range: {}", value)]));
+        assert!(validators.if_range_matches(&request(&[])));
+        assert!(if_range("\"abc\""));
+        assert!(!if_range("W/\"abc\""));
+        assert!(!if_range("\"other\""));
+        assert!(if_range("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert!(!if_range("Mon, 07 Nov 1994 00:00:00 GMT"));
+        assert!(!if_range("garbage"));
+    }
+
+    #[test]
+    fn weak_etags_never_satisfy_if_range() {
+        let validators = Validators {
+            etag: "W/\"abc\"".to_string(),
+            last_modified: None,
+        };
+        assert!(!validators.if_range_matches(&request(&["If-Range: W/\"abc\""])));
+        assert!(validators.is_not_modified(&request(&["If-None-Match: \"abc\""])));
+    }
+
+    #[test]
+    fn etag_from_metadata() {
+        let path = std::env::temp_dir().join(format!("simple_http_validators_{}", std::process::id()));
+        std::fs::write(&path, b"12345").unwrap();
+        let validators = Validators::from_metadata(&std::fs::metadata(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(validators.etag.starts_with('"') && validators.etag.ends_with("-5\""));
+        assert!(validators.last_modified_header().is_some());
+    }
+}